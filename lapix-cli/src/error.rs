@@ -0,0 +1,20 @@
+use lapix::Error as LapixError;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    LapixError(#[from] LapixError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid JSON script: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid RON script: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Event #{0} ({1:?}) failed: {2}")]
+    EventFailed(usize, Box<lapix::Event>, LapixError),
+    #[error("Wrong arguments\n\n{0}")]
+    Usage(&'static str),
+}
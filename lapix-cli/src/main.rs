@@ -0,0 +1,104 @@
+use lapix::{project, Bitmap, Event, LoadProject, RgbaImage, SaveProject, State};
+use std::path::{Path, PathBuf};
+
+mod error;
+mod script;
+
+use error::{Error, Result};
+
+const USAGE: &str = "Usage: lapix <INPUT> <SCRIPT> [OUTPUT]
+
+  INPUT   image (e.g. PNG), project file (.tarsila), project archive
+          (.zip) or Aseprite file (.ase, .aseprite) to edit
+  SCRIPT  list of events to apply, in JSON or RON (.ron) format
+  OUTPUT  where to write the result: a project if the extension is
+          .tarsila, a project archive if it's .zip, an Aseprite file if
+          it's .ase or .aseprite, an animation of the spritesheet frames if
+          it's .gif, otherwise an image";
+
+const PROJECT_EXTENSION: &str = "tarsila";
+const ARCHIVE_EXTENSION: &str = "zip";
+const ASEPRITE_EXTENSIONS: [&str; 2] = ["ase", "aseprite"];
+const GIF_EXTENSION: &str = "gif";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("ERROR: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    let (input, script, output) = match args.as_slice() {
+        [input, script] => (input, script, None),
+        [input, script, output] => (input, script, Some(output)),
+        _ => return Err(Error::Usage(USAGE)),
+    };
+
+    let mut state = open(Path::new(input))?;
+    let mut events = script::load(Path::new(script))?;
+
+    if let Some(output) = output {
+        let output = PathBuf::from(output);
+        events.push(match extension(&output) {
+            Some(PROJECT_EXTENSION) => Event::SaveProject(output),
+            Some(ARCHIVE_EXTENSION) => Event::SaveProjectArchive(output),
+            Some(ext) if ASEPRITE_EXTENSIONS.contains(&ext) => Event::ExportAseprite(output),
+            Some(GIF_EXTENSION) => Event::ExportGif(output, Default::default()),
+            _ => Event::Save(output),
+        });
+    }
+
+    for (i, event) in events.into_iter().enumerate() {
+        state
+            .execute(event.clone())
+            .map_err(|e| Error::EventFailed(i, Box::new(event), e))?;
+        print_warnings(&mut state);
+    }
+
+    Ok(())
+}
+
+/// Create a [`State`] from a project file or an image file
+fn open(path: &Path) -> Result<State<RgbaImage>> {
+    let load_fn = Some(LoadProject(project::load));
+    let save_fn = Some(SaveProject(project::save));
+
+    let load_event = match extension(path) {
+        Some(PROJECT_EXTENSION) => Some(Event::LoadProject(path.into())),
+        Some(ARCHIVE_EXTENSION) => Some(Event::LoadProjectArchive(path.into())),
+        Some(ext) if ASEPRITE_EXTENSIONS.contains(&ext) => Some(Event::ImportAseprite(path.into())),
+        _ => None,
+    };
+
+    if let Some(event) = load_event {
+        let mut state = State::new((1, 1).into(), load_fn, save_fn);
+        state.execute(event)?;
+        print_warnings(&mut state);
+
+        return Ok(state);
+    }
+
+    let img = RgbaImage::try_from_file_bytes(std::fs::read(path)?)?;
+    let mut state = State::new(img.size(), load_fn, save_fn);
+    state.canvas_mut().set_img(img);
+
+    Ok(state)
+}
+
+fn print_warnings(state: &mut State<RgbaImage>) {
+    for warning in state.take_warnings() {
+        eprintln!("WARN: {warning}");
+    }
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|ext| ext.to_str())
+}
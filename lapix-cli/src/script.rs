@@ -0,0 +1,61 @@
+use crate::Result;
+use lapix::Event;
+use std::path::Path;
+
+/// Read a list of [`Event`]s from a script file. The format is RON if the
+/// file has a `.ron` extension, otherwise JSON.
+pub fn load(path: &Path) -> Result<Vec<Event>> {
+    let text = std::fs::read_to_string(path)?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ron") => parse_ron(&text),
+        _ => parse_json(&text),
+    }
+}
+
+fn parse_json(text: &str) -> Result<Vec<Event>> {
+    Ok(serde_json::from_str(text)?)
+}
+
+fn parse_ron(text: &str) -> Result<Vec<Event>> {
+    Ok(ron::from_str(text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapix::{Color, Point, Size};
+
+    fn expected() -> Vec<Event> {
+        vec![
+            Event::ResizeCanvas(Size::new(32, 16)),
+            Event::SetMainColor(Color::new(255, 0, 0, 255)),
+            Event::Bucket(Point::new(0, 0)),
+            Event::Save("out.png".into()),
+        ]
+    }
+
+    #[test]
+    fn json() {
+        let text = r#"[
+            { "ResizeCanvas": { "x": 32, "y": 16 } },
+            { "SetMainColor": { "r": 255, "g": 0, "b": 0, "a": 255 } },
+            { "Bucket": { "x": 0, "y": 0 } },
+            { "Save": "out.png" }
+        ]"#;
+
+        assert_eq!(parse_json(text).unwrap(), expected());
+    }
+
+    #[test]
+    fn ron() {
+        let text = r#"[
+            ResizeCanvas((x: 32, y: 16)),
+            SetMainColor((r: 255, g: 0, b: 0, a: 255)),
+            Bucket((x: 0, y: 0)),
+            Save("out.png"),
+        ]"#;
+
+        assert_eq!(parse_ron(text).unwrap(), expected());
+    }
+}
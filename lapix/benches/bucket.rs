@@ -0,0 +1,182 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use lapix::{BucketOptions, Canvas, Color, Event, Point, RgbaImage, Size, State};
+
+const SIDE: i32 = 512;
+
+/// A canvas whose background is split by vertical walls, each with a gap at
+/// alternating ends, so the fill has to snake around them
+fn maze() -> State<RgbaImage> {
+    let mut state = State::new(Size::new(SIDE, SIDE), None, None);
+
+    for x in (2..SIDE).step_by(4) {
+        let (start, end) = match x % 8 {
+            2 => (Point::new(x, 0), Point::new(x, SIDE - 2)),
+            _ => (Point::new(x, 1), Point::new(x, SIDE - 1)),
+        };
+        state.execute(Event::LineStart(start)).unwrap();
+        state.execute(Event::LineEnd(end)).unwrap();
+    }
+    state
+        .execute(Event::SetMainColor((255, 0, 0, 255).into()))
+        .unwrap();
+
+    state
+}
+
+fn maze_canvas() -> Canvas<RgbaImage> {
+    let mut canvas = Canvas::new(Size::new(SIDE, SIDE));
+    canvas.set_img(maze().canvas().inner().clone());
+
+    canvas
+}
+
+fn empty_canvas() -> Canvas<RgbaImage> {
+    Canvas::new(Size::new(SIDE, SIDE))
+}
+
+/// The pixel by pixel fill of `Canvas::bucket` before the scanline fill
+/// replaced it, as it was in lapix 0.1.2 (only turned into a function)
+fn baseline_bucket(
+    canvas: &mut Canvas<RgbaImage>,
+    p: Point<i32>,
+    color: Color,
+) -> Vec<(Point<i32>, Color)> {
+    let old_color = canvas.pixel(p);
+
+    if color == old_color {
+        return Vec::new();
+    }
+
+    let w = canvas.width() as usize;
+    let h = canvas.height() as usize;
+
+    let mut marked = vec![false; w * h];
+    let mut visit = vec![(p.x, p.y)];
+
+    let mut reversals = Vec::new();
+
+    loop {
+        if visit.is_empty() {
+            break;
+        }
+
+        let mut new_visit = Vec::new();
+        while let Some((vx, vy)) = visit.pop() {
+            marked[(vy as usize) * w + vx as usize] = true;
+
+            if let Some(action) = canvas.set_pixel((vx, vy).into(), color) {
+                reversals.push(action);
+            }
+
+            for (nx, ny) in neighbors(canvas, vx, vy).into_iter().flatten() {
+                let ind = (ny as usize) * w + nx as usize;
+                if canvas.pixel((nx, ny).into()) == old_color && !marked[ind] {
+                    new_visit.push((nx, ny));
+                    marked[ind] = true;
+                }
+            }
+        }
+
+        visit.append(&mut new_visit);
+    }
+
+    reversals
+}
+
+fn neighbors(canvas: &Canvas<RgbaImage>, x: i32, y: i32) -> [Option<(i32, i32)>; 4] {
+    let mut neighbors = [None; 4];
+    let w = canvas.width();
+    let h = canvas.height();
+
+    if x + 1 < w {
+        neighbors[0] = Some((x + 1, y));
+    }
+    if x > 0 {
+        neighbors[1] = Some((x - 1, y));
+    }
+    if y + 1 < h {
+        neighbors[2] = Some((x, y + 1));
+    }
+    if y > 0 {
+        neighbors[3] = Some((x, y - 1));
+    }
+
+    neighbors
+}
+
+fn bucket(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bucket");
+    group.sample_size(20);
+
+    group.bench_function("empty canvas", |b| {
+        b.iter_batched(
+            || State::<RgbaImage>::new(Size::new(SIDE, SIDE), None, None),
+            |mut state| state.execute(Event::Bucket(Point::ZERO)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("maze", |b| {
+        b.iter_batched(
+            maze,
+            |mut state| state.execute(Event::Bucket(Point::ZERO)).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("empty canvas and undo", |b| {
+        b.iter_batched(
+            || State::<RgbaImage>::new(Size::new(SIDE, SIDE), None, None),
+            |mut state| {
+                state.execute(Event::Bucket(Point::ZERO)).unwrap();
+                state.execute(Event::Undo).unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+/// The scanline fill against the pixel by pixel fill it replaced, on the same
+/// canvases, filling and then restoring the pixels from the reversals
+fn fill_algorithms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fill algorithm");
+    group.sample_size(20);
+    let red = (255, 0, 0, 255).into();
+    let options = BucketOptions::default();
+    let canvases = [
+        ("empty canvas", empty_canvas as fn() -> _),
+        ("maze", maze_canvas),
+    ];
+
+    for (name, canvas) in canvases {
+        group.bench_function(format!("scanline, {name}"), |b| {
+            b.iter_batched(
+                canvas,
+                |mut canvas| {
+                    for span in canvas.bucket(Point::ZERO, red, options) {
+                        canvas.set_span(span);
+                    }
+                    canvas
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(format!("pixel by pixel, {name}"), |b| {
+            b.iter_batched(
+                canvas,
+                |mut canvas| {
+                    for (p, color) in baseline_bucket(&mut canvas, Point::ZERO, red) {
+                        canvas.set_pixel(p, color);
+                    }
+                    canvas
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bucket, fill_algorithms);
+criterion_main!(benches);
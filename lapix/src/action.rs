@@ -1,8 +1,16 @@
-use crate::{Bitmap, CanvasEffect, Color, Layer, Layers, Point};
+use crate::tile::TileDiff;
+use crate::{
+    Bitmap, CanvasEffect, Color, ColorMode, DirtyRect, Layer, Layers, Palette, Point, Rect, Size,
+    Span,
+};
 use std::fmt::Debug;
+use std::mem::{size_of, size_of_val};
 
 pub type LayerIndex = usize;
 
+/// Pixel changes in a layer, not yet compacted into a [`TileDiff`]
+type LayerPixels = (LayerIndex, Vec<(Point<i32>, Color)>);
+
 pub struct Action<IMG>(Vec<AtomicAction<IMG>>);
 
 impl<IMG> Default for Action<IMG> {
@@ -40,14 +48,68 @@ impl<IMG: Bitmap> Action<IMG> {
         }
     }
 
-    pub fn apply(mut self, layers: &mut Layers<IMG>) -> CanvasEffect {
+    /// Merge consecutive pixel changes on the same layer into a single
+    /// [`TileDiff`], which takes much less memory
+    pub fn compact(&mut self) {
+        fn flush<IMG>(actions: &mut Vec<AtomicAction<IMG>>, pixels: Option<LayerPixels>) {
+            if let Some((i, pixels)) = pixels {
+                let diff = TileDiff::from_pixels(pixels);
+                if !diff.is_empty() {
+                    actions.push(AtomicAction::SetTiles(i, diff));
+                }
+            }
+        }
+
+        let mut actions = Vec::new();
+        let mut pixels: Option<LayerPixels> = None;
+
+        for action in self.0.drain(..) {
+            match action {
+                AtomicAction::SetPixel(i, p, color) => match pixels.as_mut() {
+                    Some((layer, values)) if *layer == i => values.push((p, color)),
+                    _ => {
+                        flush(&mut actions, pixels.take());
+                        pixels = Some((i, vec![(p, color)]));
+                    }
+                },
+                action => {
+                    flush(&mut actions, pixels.take());
+                    actions.push(action);
+                }
+            }
+        }
+        flush(&mut actions, pixels);
+
+        self.0 = actions;
+    }
+
+    /// Get an estimate of the memory taken by this action, in bytes
+    pub fn memory_size(&self) -> usize {
+        self.0.iter().map(AtomicAction::memory_size).sum()
+    }
+
+    /// Apply this action to the layers, palette, main color, spritesheet and
+    /// color mode. Returns the [`CanvasEffect`] and the action that reverses
+    /// this one (used to redo after an undo, and vice versa).
+    pub fn apply(
+        mut self,
+        layers: &mut Layers<IMG>,
+        palette: &mut Palette,
+        main_color: &mut Color,
+        spritesheet: &mut Size<u8>,
+        color_mode: &mut ColorMode,
+    ) -> (CanvasEffect, Self) {
         let mut effect = CanvasEffect::None;
+        let mut reverse = Self::default();
 
         while let Some(action) = self.0.pop() {
-            effect = action.apply(layers);
+            let (fx, reverse_action) =
+                action.apply(layers, palette, main_color, spritesheet, color_mode);
+            effect = effect.merge(fx);
+            reverse.push(reverse_action);
         }
 
-        effect
+        (effect, reverse)
     }
 }
 
@@ -55,7 +117,18 @@ pub enum AtomicAction<IMG> {
     SetPixel(LayerIndex, Point<i32>, Color),
     DestroyLayer(LayerIndex),
     CreateLayer(LayerIndex, Layer<IMG>),
-    SetLayerCanvas(LayerIndex, IMG),
+    SetTiles(LayerIndex, TileDiff),
+    /// Set rows of pixels of a layer. The spans must not overlap.
+    SetSpans(LayerIndex, Vec<Span>),
+    ResizeLayer(LayerIndex, Size<i32>),
+    SetLayerVisibility(LayerIndex, bool),
+    SetLayerOpacity(LayerIndex, u8),
+    SwapLayers(LayerIndex, LayerIndex),
+    SetActiveLayer(LayerIndex),
+    SetPalette(Palette),
+    SetMainColor(Color),
+    SetSpritesheet(Size<u8>),
+    SetColorMode(ColorMode),
 }
 
 impl<IMG> Debug for AtomicAction<IMG> {
@@ -69,7 +142,25 @@ impl<IMG> Debug for AtomicAction<IMG> {
                 .finish(),
             Self::DestroyLayer(i) => f.debug_tuple("DestroyLayer").field(&i).finish(),
             Self::CreateLayer(i, _) => f.debug_tuple("CreateLayer").field(&i).finish(),
-            Self::SetLayerCanvas(i, _) => f.debug_tuple("SetLayerCanvas").field(&i).finish(),
+            Self::SetTiles(i, _) => f.debug_tuple("SetTiles").field(&i).finish(),
+            Self::SetSpans(i, _) => f.debug_tuple("SetSpans").field(&i).finish(),
+            Self::ResizeLayer(i, s) => f.debug_tuple("ResizeLayer").field(&i).field(&s).finish(),
+            Self::SetLayerVisibility(i, v) => f
+                .debug_tuple("SetLayerVisibility")
+                .field(&i)
+                .field(&v)
+                .finish(),
+            Self::SetLayerOpacity(i, a) => f
+                .debug_tuple("SetLayerOpacity")
+                .field(&i)
+                .field(&a)
+                .finish(),
+            Self::SwapLayers(i, j) => f.debug_tuple("SwapLayers").field(&i).field(&j).finish(),
+            Self::SetActiveLayer(i) => f.debug_tuple("SetActiveLayer").field(&i).finish(),
+            Self::SetPalette(p) => f.debug_tuple("SetPalette").field(&p).finish(),
+            Self::SetMainColor(c) => f.debug_tuple("SetMainColor").field(&c).finish(),
+            Self::SetSpritesheet(s) => f.debug_tuple("SetSpritesheet").field(&s).finish(),
+            Self::SetColorMode(m) => f.debug_tuple("SetColorMode").field(&m).finish(),
         }
     }
 }
@@ -82,21 +173,122 @@ impl<IMG: Bitmap> AtomicAction<IMG> {
             .collect()
     }
 
-    pub fn apply(self, layers: &mut Layers<IMG>) -> CanvasEffect {
-        match self {
+    /// Get the actions needed to reverse the resizing of all layers, given the
+    /// images they had before being resized to a certain size
+    pub fn resize_vec(imgs: Vec<IMG>, size: Size<i32>) -> Vec<Self> {
+        let mut actions = Vec::new();
+
+        for (i, img) in imgs.into_iter().enumerate() {
+            actions.push(AtomicAction::SetTiles(
+                i,
+                TileDiff::from_img_outside(&img, size),
+            ));
+            actions.push(AtomicAction::ResizeLayer(i, img.size()));
+        }
+
+        actions
+    }
+
+    /// Get an estimate of the memory taken by this action, in bytes
+    pub fn memory_size(&self) -> usize {
+        let heap = match self {
+            Self::CreateLayer(_, layer) => layer.canvas().bytes().len(),
+            Self::SetTiles(_, diff) => diff.memory_size(),
+            Self::SetSpans(_, spans) => size_of_val(spans.as_slice()),
+            Self::SetPalette(palette) => palette.memory_size(),
+            _ => 0,
+        };
+
+        size_of::<Self>() + heap
+    }
+
+    /// Apply this action to the layers, palette, main color, spritesheet and
+    /// color mode. Returns the [`CanvasEffect`] and the atomic action needed to
+    /// reverse it
+    pub fn apply(
+        self,
+        layers: &mut Layers<IMG>,
+        palette: &mut Palette,
+        main_color: &mut Color,
+        spritesheet: &mut Size<u8>,
+        color_mode: &mut ColorMode,
+    ) -> (CanvasEffect, Self) {
+        let reverse = match self {
             Self::SetPixel(i, p, color) => {
+                let old = layers.canvas_at(i).pixel(p);
                 layers.canvas_at_mut(i).set_pixel(p, color);
+                let dirty = DirtyRect::new(i, Rect::new(p.x, p.y, 1, 1));
+                return (CanvasEffect::Pixels(dirty), Self::SetPixel(i, p, old));
             }
             Self::DestroyLayer(i) => {
-                layers.delete(i);
+                let layer = layers.delete(i);
+                Self::CreateLayer(i, layer)
             }
             Self::CreateLayer(i, layer) => {
                 layers.add_at(i, layer);
+                Self::DestroyLayer(i)
             }
-            Self::SetLayerCanvas(i, img) => {
-                layers.canvas_at_mut(i).set_img(img);
+            Self::SetTiles(i, diff) => {
+                let reverse = diff.apply(layers.canvas_at_mut(i));
+                let effect = match diff.bounds(layers.canvas_at(i).rect()) {
+                    Some(rect) => CanvasEffect::Pixels(DirtyRect::new(i, rect)),
+                    None => CanvasEffect::None,
+                };
+                return (effect, Self::SetTiles(i, reverse));
             }
-        }
-        CanvasEffect::Layer
+            Self::SetSpans(i, spans) => {
+                let canvas = layers.canvas_at_mut(i);
+                let reverse = spans
+                    .iter()
+                    .flat_map(|&span| canvas.set_span(span))
+                    .collect();
+                let effect = match Span::bounds(&spans) {
+                    Some(rect) => CanvasEffect::Pixels(DirtyRect::new(i, rect)),
+                    None => CanvasEffect::None,
+                };
+                return (effect, Self::SetSpans(i, reverse));
+            }
+            Self::ResizeLayer(i, size) => {
+                let old = layers.get_mut(i).resize(size);
+                return (CanvasEffect::New, Self::ResizeLayer(i, old.size()));
+            }
+            Self::SetLayerVisibility(i, visible) => {
+                let old = layers.get(i).visible();
+                layers.set_visibility(i, visible);
+                Self::SetLayerVisibility(i, old)
+            }
+            Self::SetLayerOpacity(i, opacity) => {
+                let old = layers.get(i).opacity();
+                layers.set_opacity(i, opacity);
+                Self::SetLayerOpacity(i, old)
+            }
+            Self::SwapLayers(i, j) => {
+                layers.swap(i, j);
+                Self::SwapLayers(i, j)
+            }
+            Self::SetActiveLayer(i) => {
+                let old = layers.active_index();
+                layers.switch_to(i);
+                Self::SetActiveLayer(old)
+            }
+            Self::SetPalette(new) => {
+                let old = std::mem::replace(palette, new);
+                return (CanvasEffect::None, Self::SetPalette(old));
+            }
+            Self::SetMainColor(color) => {
+                let old = std::mem::replace(main_color, color);
+                return (CanvasEffect::None, Self::SetMainColor(old));
+            }
+            Self::SetSpritesheet(size) => {
+                let old = std::mem::replace(spritesheet, size);
+                return (CanvasEffect::None, Self::SetSpritesheet(old));
+            }
+            Self::SetColorMode(mode) => {
+                let old = std::mem::replace(color_mode, mode);
+                return (CanvasEffect::None, Self::SetColorMode(old));
+            }
+        };
+
+        (CanvasEffect::Layer, reverse)
     }
 }
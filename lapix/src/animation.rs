@@ -0,0 +1,344 @@
+//! Export of the frames of a spritesheet as animations
+
+use crate::{Bitmap, Color, Error, Result, Size, Transform};
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Alpha below which a pixel is transparent in formats that don't support
+/// partial transparency
+const ALPHA_THRESHOLD: u8 = 128;
+/// Speed of the quantization used for GIF frames that can't use the palette
+/// (from 1 to 30, slower is better)
+const QUANTIZATION_SPEED: i32 = 10;
+
+/// How an animation is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnimationOptions {
+    /// Time each frame is shown, in milliseconds. GIFs can only store it in
+    /// hundredths of a second, so it's rounded to the nearest one (at least 1)
+    pub frame_delay: u16,
+    /// How many times the animation is repeated after playing once, or `None`
+    /// to repeat it forever
+    pub repeat: Option<u16>,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            frame_delay: 100,
+            repeat: None,
+        }
+    }
+}
+
+/// Split an image into the frames of a spritesheet, from left to right, then
+/// top to bottom. Returns the size of the frames, and their RGBA pixels.
+pub(crate) fn frames<IMG: Bitmap>(img: &IMG, spritesheet: Size<u8>) -> (Size<i32>, Vec<Vec<u8>>) {
+    let size = Size::new(
+        img.width() / spritesheet.x as i32,
+        img.height() / spritesheet.y as i32,
+    );
+    let bytes = img.bytes();
+    let row_len = img.width() as usize * 4;
+    let mut frames = Vec::new();
+
+    for row in 0..spritesheet.y as usize {
+        for col in 0..spritesheet.x as usize {
+            let mut frame = Vec::with_capacity(size.x as usize * size.y as usize * 4);
+            for y in 0..size.y as usize {
+                let start = (row * size.y as usize + y) * row_len + col * size.x as usize * 4;
+                frame.extend_from_slice(&bytes[start..start + size.x as usize * 4]);
+            }
+            frames.push(frame);
+        }
+    }
+
+    (size, frames)
+}
+
+/// Encode the frames of a spritesheet as an animated GIF. If the palette has
+/// up to 256 colors, every pixel is drawn with the closest color of the
+/// palette. Otherwise (or if there are exactly 256 colors, but no index is left
+/// for transparent pixels), the colors of each frame are quantized separately.
+pub(crate) fn encode_gif<IMG: Bitmap>(
+    img: &IMG,
+    spritesheet: Size<u8>,
+    palette: &[Color],
+    options: AnimationOptions,
+) -> Result<Vec<u8>> {
+    let (size, frames) = frames(img, spritesheet);
+    if size.x > u16::MAX as i32 || size.y > u16::MAX as i32 {
+        return Err(Error::AnimationTooBig);
+    }
+    let (w, h) = (size.x as u16, size.y as u16);
+
+    let transparent = frames
+        .iter()
+        .flat_map(|frame| frame.chunks(4))
+        .any(|pixel| pixel[3] < ALPHA_THRESHOLD);
+    let use_palette = !palette.is_empty() && palette.len() + transparent as usize <= 256;
+
+    let mut global_palette = Vec::new();
+    if use_palette {
+        for color in palette {
+            global_palette.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+        if transparent {
+            global_palette.extend_from_slice(&[0, 0, 0]);
+        }
+    }
+
+    let mut encoder = Encoder::new(Vec::new(), w, h, &global_palette)?;
+    encoder.set_repeat(match options.repeat {
+        Some(n) => Repeat::Finite(n),
+        None => Repeat::Infinite,
+    })?;
+
+    for mut pixels in frames {
+        let mut frame = match use_palette {
+            true => {
+                let transparent_index = palette.len() as u8;
+                let indices: Vec<_> = pixels
+                    .chunks(4)
+                    .map(|p| match p[3] < ALPHA_THRESHOLD {
+                        true => transparent_index,
+                        false => {
+                            let color = Color::new(p[0], p[1], p[2], p[3]);
+                            Transform::nearest_color(palette, color) as u8
+                        }
+                    })
+                    .collect();
+                let transparent_index = transparent.then_some(transparent_index);
+                Frame::from_indexed_pixels(w, h, &indices, transparent_index)
+            }
+            false => {
+                for pixel in pixels.chunks_mut(4) {
+                    if pixel[3] < ALPHA_THRESHOLD {
+                        pixel[3] = 0;
+                    }
+                }
+                Frame::from_rgba_speed(w, h, &mut pixels, QUANTIZATION_SPEED)
+            }
+        };
+        frame.delay = gif_delay(options.frame_delay);
+        // Clear each frame before drawing the next, or transparent pixels would
+        // show the previous frame
+        frame.dispose = DisposalMethod::Background;
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(encoder.into_inner()?)
+}
+
+/// Convert a frame delay in milliseconds to the hundredths of a second GIF
+/// frames use. A delay of 0 is avoided, since most viewers would play it as
+/// fast as possible or with a default delay.
+fn gif_delay(ms: u16) -> u16 {
+    ((ms as u32 + 5) / 10).max(1) as u16
+}
+
+/// Encode the frames of a spritesheet as an animated PNG (APNG)
+pub(crate) fn encode_apng<IMG: Bitmap>(
+    img: &IMG,
+    spritesheet: Size<u8>,
+    options: AnimationOptions,
+) -> Result<Vec<u8>> {
+    let (size, frames) = frames(img, spritesheet);
+    let mut bytes = Vec::new();
+
+    let mut encoder = png_encoder(&mut bytes, size);
+    // The number of plays includes the first one, and 0 means forever
+    let plays = match options.repeat {
+        Some(n) => n as u32 + 1,
+        None => 0,
+    };
+    encoder.set_animated(frames.len() as u32, plays)?;
+    encoder.set_frame_delay(options.frame_delay, 1000)?;
+    encoder.set_dispose_op(DisposeOp::Background)?;
+    encoder.set_blend_op(BlendOp::Source)?;
+
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(&frame)?;
+    }
+    writer.finish()?;
+
+    Ok(bytes)
+}
+
+/// Encode each frame of a spritesheet as a PNG image. Returns the name of the
+/// file of each frame, which is the name of `path` followed by the number of
+/// the frame (e.g. `walk_000.png`, `walk_001.png`, etc., for `walk.png`), and
+/// its PNG bytes.
+pub(crate) fn encode_png_sequence<IMG: Bitmap>(
+    img: &IMG,
+    spritesheet: Size<u8>,
+    path: &Path,
+) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let (size, frames) = frames(img, spritesheet);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let digits = (frames.len().saturating_sub(1)).to_string().len().max(3);
+    let mut files = Vec::new();
+
+    for (i, frame) in frames.iter().enumerate() {
+        let mut bytes = Vec::new();
+        let mut writer = png_encoder(&mut bytes, size).write_header()?;
+        writer.write_image_data(frame)?;
+        writer.finish()?;
+
+        let name = format!("{stem}_{i:0digits$}.png");
+        files.push((path.with_file_name(name), bytes));
+    }
+
+    Ok(files)
+}
+
+fn png_encoder(bytes: &mut Vec<u8>, size: Size<i32>) -> png::Encoder<'_, &mut Vec<u8>> {
+    let mut encoder = png::Encoder::new(bytes, size.x as u32, size.y as u32);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+
+    encoder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::color::TRANSPARENT;
+    use crate::Point;
+    use gif::{ColorOutput, DecodeOptions};
+    use test_case::test_case;
+
+    #[test]
+    fn spritesheet_frames() {
+        let mut img = TestImage::new((4, 4).into(), TRANSPARENT);
+        img.set_pixel(Point::new(1, 0), Color::new(1, 1, 1, 255));
+        img.set_pixel(Point::new(2, 3), Color::new(2, 2, 2, 255));
+
+        let (size, frames) = frames(&img, (2, 2).into());
+        assert_eq!(size, Size::new(2, 2));
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0][4..8], [1, 1, 1, 255]);
+        assert_eq!(frames[3][8..12], [2, 2, 2, 255]);
+        assert!(frames[1].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn gif_with_palette() {
+        let palette = [Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255)];
+        let mut img = TestImage::new((4, 1).into(), TRANSPARENT);
+        img.set_pixel(Point::new(0, 0), Color::new(250, 10, 10, 255));
+        img.set_pixel(Point::new(3, 0), Color::new(0, 0, 200, 255));
+        let options = AnimationOptions {
+            frame_delay: 200,
+            repeat: Some(3),
+        };
+
+        let bytes = encode_gif(&img, (2, 1).into(), &palette, options).unwrap();
+
+        let mut decoder = DecodeOptions::new();
+        decoder.set_color_output(ColorOutput::Indexed);
+        let mut decoder = decoder.read_info(bytes.as_slice()).unwrap();
+        assert_eq!(
+            decoder.global_palette().unwrap()[..6],
+            [255, 0, 0, 0, 0, 255]
+        );
+
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!((frame.width, frame.height), (2, 1));
+        assert_eq!(frame.delay, 20);
+        assert_eq!(frame.transparent, Some(2));
+        assert_eq!(*frame.buffer, [0, 2]);
+
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(*frame.buffer, [2, 1]);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+
+    #[test_case(0, 1)]
+    #[test_case(4, 1)]
+    #[test_case(14, 1)]
+    #[test_case(15, 2)]
+    #[test_case(104, 10)]
+    #[test_case(u16::MAX, 6554)]
+    fn gif_frame_delay(ms: u16, hundredths: u16) {
+        assert_eq!(gif_delay(ms), hundredths);
+    }
+
+    #[test]
+    fn gif_with_short_delay() {
+        let img = TestImage::new((1, 1).into(), Color::new(0, 0, 0, 255));
+        let options = AnimationOptions {
+            frame_delay: 5,
+            repeat: None,
+        };
+
+        let bytes = encode_gif(&img, (1, 1).into(), &[], options).unwrap();
+
+        let mut decoder = DecodeOptions::new().read_info(bytes.as_slice()).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.delay, 1);
+    }
+
+    #[test]
+    fn gif_without_palette() {
+        let palette = vec![Color::new(0, 0, 0, 255); 256];
+        let mut img = TestImage::new((2, 1).into(), Color::new(0, 200, 0, 255));
+        img.set_pixel(Point::new(1, 0), TRANSPARENT);
+
+        let bytes = encode_gif(&img, (1, 1).into(), &palette, Default::default()).unwrap();
+
+        let mut decoder = DecodeOptions::new();
+        decoder.set_color_output(ColorOutput::RGBA);
+        let mut decoder = decoder.read_info(bytes.as_slice()).unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.buffer[..4], [0, 200, 0, 255]);
+        assert_eq!(frame.buffer[7], 0);
+    }
+
+    #[test]
+    fn apng() {
+        let mut img = TestImage::new((4, 2).into(), TRANSPARENT);
+        img.set_pixel(Point::new(3, 1), Color::new(10, 20, 30, 40));
+        let options = AnimationOptions {
+            frame_delay: 50,
+            repeat: Some(1),
+        };
+
+        let bytes = encode_apng(&img, (2, 1).into(), options).unwrap();
+
+        let mut decoder = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let info = decoder.info();
+        let control = info.animation_control().unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (2, 2));
+        assert_eq!((info.width, info.height), (2, 2));
+
+        let mut buf = vec![0; decoder.output_buffer_size()];
+        decoder.next_frame(&mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 0));
+        decoder.next_frame(&mut buf).unwrap();
+        assert_eq!(buf[12..16], [10, 20, 30, 40]);
+        let frame = decoder.info().frame_control().unwrap();
+        assert_eq!((frame.delay_num, frame.delay_den), (50, 1000));
+    }
+
+    #[test]
+    fn png_sequence() {
+        let mut img = TestImage::new((3, 1).into(), TRANSPARENT);
+        img.set_pixel(Point::new(2, 0), Color::new(1, 2, 3, 255));
+
+        let files = encode_png_sequence(&img, (3, 1).into(), Path::new("out/walk.png")).unwrap();
+
+        let names: Vec<_> = files.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(
+            names,
+            ["out/walk_000.png", "out/walk_001.png", "out/walk_002.png"].map(PathBuf::from)
+        );
+        let frame = TestImage::try_from_file_bytes(files[2].1.clone()).unwrap();
+        assert_eq!(frame.size(), Size::new(1, 1));
+        assert_eq!(frame.pixel(Point::ZERO), Color::new(1, 2, 3, 255));
+    }
+}
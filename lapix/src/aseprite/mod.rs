@@ -0,0 +1,56 @@
+//! Support for files of the [Aseprite](https://www.aseprite.org/) editor
+//! (`.ase` and `.aseprite`). See the
+//! [file format specification](https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md).
+
+use crate::{Layers, Palette, Size};
+
+mod read;
+mod write;
+
+pub use read::read;
+pub use write::write;
+
+const FILE_MAGIC: u16 = 0xa5e0;
+const FRAME_MAGIC: u16 = 0xf1fa;
+const HEADER_SIZE: usize = 128;
+const FRAME_HEADER_SIZE: usize = 16;
+const CHUNK_HEADER_SIZE: usize = 6;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_OLD_PALETTE_64: u16 = 0x0011;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_CEL_EXTRA: u16 = 0x2006;
+const CHUNK_COLOR_PROFILE: u16 = 0x2007;
+const CHUNK_EXTERNAL_FILES: u16 = 0x2008;
+const CHUNK_MASK: u16 = 0x2016;
+const CHUNK_PATH: u16 = 0x2017;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_USER_DATA: u16 = 0x2020;
+const CHUNK_SLICE: u16 = 0x2022;
+const CHUNK_TILESET: u16 = 0x2023;
+
+const LAYER_FLAG_VISIBLE: u16 = 1;
+const LAYER_TYPE_NORMAL: u16 = 0;
+const LAYER_TYPE_GROUP: u16 = 1;
+
+const CEL_TYPE_RAW: u16 = 0;
+const CEL_TYPE_LINKED: u16 = 1;
+const CEL_TYPE_COMPRESSED: u16 = 2;
+
+/// Header flag that tells whether layer opacity is valid
+const FLAG_LAYER_OPACITY: u32 = 1;
+
+/// The contents of an Aseprite file, converted to what lapix supports. All
+/// the frames are laid out side by side (in as many rows as needed) in each
+/// layer, as a spritesheet.
+#[derive(Debug)]
+pub struct Sprite<IMG> {
+    pub layers: Layers<IMG>,
+    pub palette: Palette,
+    /// Number of columns and rows of frames
+    pub spritesheet: Size<u8>,
+    /// Parts of the file that could not be imported
+    pub warnings: Vec<String>,
+}
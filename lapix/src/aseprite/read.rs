@@ -0,0 +1,867 @@
+use super::*;
+use crate::{Bitmap, Color, Error, Layer, Point, Result};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+/// Flag of the layer at the bottom of the sprite, which has no transparency
+const LAYER_FLAG_BACKGROUND: u16 = 8;
+const LAYER_TYPE_TILEMAP: u16 = 2;
+const BLEND_MODE_NORMAL: u16 = 0;
+const CEL_TYPE_TILEMAP: u16 = 3;
+/// Largest number of pixels of all layers together (256 MB of RGBA), so that
+/// a malformed file can't make us allocate huge images
+const MAX_PIXELS: usize = 1 << 26;
+/// Palette chunks can have up to 65536 colors, and the old palette chunks up
+/// to 256
+const MAX_PALETTE: usize = 1 << 16;
+const MAX_OLD_PALETTE: usize = 256;
+
+/// Read an Aseprite file. Frames are laid out in a single row (or in more rows,
+/// if there are more than 255 frames), and groups of layers are flattened.
+/// Anything in the file that can't be represented in lapix (like tilemaps,
+/// tags or slices) is reported in [`Sprite::warnings`].
+pub fn read<IMG: Bitmap>(bytes: &[u8]) -> Result<Sprite<IMG>> {
+    let mut file = File::parse(bytes)?;
+    let mut warnings = std::mem::take(&mut file.warnings);
+
+    let frame_size = Size::new(file.header.width as i32, file.header.height as i32);
+    let frames = file.header.frames as usize;
+    let cols = frames.clamp(1, u8::MAX as usize);
+    let rows = frames.div_ceil(cols);
+    if rows > u8::MAX as usize {
+        return Err(Error::InvalidAseprite("too many frames"));
+    }
+    let size = Size::new(frame_size.x * cols as i32, frame_size.y * rows as i32);
+    let normal_layers = file
+        .layers
+        .iter()
+        .filter(|layer| layer.kind == LAYER_TYPE_NORMAL)
+        .count();
+    if normal_layers == 0 {
+        return Err(Error::InvalidAseprite("there are no image layers"));
+    }
+    if size.x as usize * size.y as usize * normal_layers > MAX_PIXELS {
+        return Err(Error::InvalidAseprite("the sprite is too big"));
+    }
+
+    // Map the index of each Aseprite layer to the index of the lapix layer it
+    // was imported as, if any
+    let mut indices = Vec::new();
+    let mut layers = Vec::new();
+    // Visibility of the groups that contain the current layer, by child level
+    let mut groups: Vec<bool> = Vec::new();
+
+    for layer in &file.layers {
+        groups.truncate(layer.child_level as usize);
+        let visible = layer.visible && groups.iter().all(|&v| v);
+
+        match layer.kind {
+            LAYER_TYPE_NORMAL => {
+                if layer.blend_mode != BLEND_MODE_NORMAL {
+                    warnings.push(format!(
+                        "Layer \"{}\" uses a blend mode that is not supported, \
+                        it was imported with normal blending",
+                        layer.name
+                    ));
+                }
+                let opacity = match file.header.flags & FLAG_LAYER_OPACITY {
+                    0 => u8::MAX,
+                    _ => layer.opacity,
+                };
+                let pixels = vec![0; size.x as usize * size.y as usize * 4];
+                indices.push(Some(layers.len()));
+                layers.push((layer, visible, opacity, pixels));
+            }
+            LAYER_TYPE_GROUP => {
+                warn(
+                    &mut warnings,
+                    "Layer groups are not supported, their layers were imported ungrouped",
+                );
+                indices.push(None);
+            }
+            _ => {
+                warnings.push(format!(
+                    "Tilemap layer \"{}\" is not supported and was not imported",
+                    layer.name
+                ));
+                indices.push(None);
+            }
+        }
+
+        if layer.kind == LAYER_TYPE_GROUP {
+            groups.push(visible);
+        }
+    }
+
+    for cel in &file.cels {
+        let (layer, _, _, pixels) = match indices.get(cel.layer) {
+            Some(Some(i)) => &mut layers[*i],
+            _ => continue,
+        };
+        // Linked cels share the image, position and opacity of another cel
+        let source = match cel.content {
+            CelContent::Linked(frame) => file
+                .cels
+                .iter()
+                .find(|c| c.layer == cel.layer && c.frame == frame),
+            CelContent::Image(..) => Some(cel),
+        };
+        let (source, cel_size, data) = match source {
+            Some(
+                c @ Cel {
+                    content: CelContent::Image(size, data),
+                    ..
+                },
+            ) => (c, *size, data),
+            _ => return Err(Error::InvalidAseprite("linked cel not found")),
+        };
+
+        let origin = Point::new(
+            (cel.frame % cols) as i32 * frame_size.x,
+            (cel.frame / cols) as i32 * frame_size.y,
+        );
+        let depth = (file.header.depth / 8) as usize;
+        if data.len() < cel_size.x as usize * cel_size.y as usize * depth {
+            return Err(Error::InvalidAseprite("cel image is too short"));
+        }
+
+        for y in 0..cel_size.y {
+            for x in 0..cel_size.x {
+                let p = Point::new(source.x + x, source.y + y);
+                if p.x < 0 || p.y < 0 || p.x >= frame_size.x || p.y >= frame_size.y {
+                    continue;
+                }
+
+                let i = (y * cel_size.x + x) as usize * depth;
+                let color = file
+                    .color(&data[i..i + depth], layer.background)
+                    .with_multiplied_alpha(source.opacity);
+                let p = origin + p;
+                let j = (p.y * size.x + p.x) as usize * 4;
+                pixels[j..j + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+            }
+        }
+    }
+
+    let layers: Vec<_> = layers
+        .into_iter()
+        .map(|(_, visible, opacity, pixels)| {
+            let mut layer = Layer::new(Size::ZERO);
+            layer.canvas_mut().set_img(IMG::from_parts(size, &pixels));
+            layer.set_visibility(visible);
+            layer.set_opacity(opacity);
+
+            layer
+        })
+        .collect();
+    let active = layers.len() - 1;
+
+    let palette = match file.palette.is_empty() {
+        true => Palette::default(),
+        false => Palette::new(file.palette),
+    };
+
+    Ok(Sprite {
+        layers: Layers::from_vec(layers, active),
+        palette,
+        spritesheet: Size::new(cols as u8, rows as u8),
+        warnings,
+    })
+}
+
+/// Add a warning, unless it was already added
+fn warn(warnings: &mut Vec<String>, warning: &str) {
+    if !warnings.iter().any(|w| w == warning) {
+        warnings.push(warning.to_owned());
+    }
+}
+
+struct Header {
+    frames: u16,
+    width: u16,
+    height: u16,
+    /// Bits per pixel: 32 (RGBA), 16 (grayscale) or 8 (indexed)
+    depth: u16,
+    flags: u32,
+    transparent_index: u8,
+}
+
+struct AseLayer {
+    name: String,
+    visible: bool,
+    background: bool,
+    kind: u16,
+    child_level: u16,
+    blend_mode: u16,
+    opacity: u8,
+}
+
+struct Cel {
+    layer: usize,
+    frame: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    content: CelContent,
+}
+
+enum CelContent {
+    /// Size and uncompressed pixels of the image
+    Image(Size<i32>, Vec<u8>),
+    /// Same as the cel of this layer at another frame
+    Linked(usize),
+}
+
+/// The parts of an Aseprite file that can be imported, before the pixels are
+/// converted (which in indexed mode needs the palette, that can come after the
+/// cels)
+struct File {
+    header: Header,
+    layers: Vec<AseLayer>,
+    cels: Vec<Cel>,
+    palette: Vec<Color>,
+    warnings: Vec<String>,
+}
+
+impl File {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let header = Self::parse_header(&mut reader)?;
+        let mut file = Self {
+            header,
+            layers: Vec::new(),
+            cels: Vec::new(),
+            palette: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut old_palette = Vec::new();
+
+        for frame in 0..file.header.frames as usize {
+            let size = reader.dword()? as usize;
+            if size < FRAME_HEADER_SIZE {
+                return Err(Error::InvalidAseprite("invalid frame header"));
+            }
+            let mut frame_reader = Reader::new(reader.take(size - 4)?);
+            if frame_reader.word()? != FRAME_MAGIC {
+                return Err(Error::InvalidAseprite("invalid frame header"));
+            }
+            let old_chunks = frame_reader.word()?;
+            // Frame duration and reserved bytes
+            frame_reader.take(4)?;
+            let chunks = match frame_reader.dword()? {
+                0 => old_chunks as u32,
+                n => n,
+            };
+
+            for _ in 0..chunks {
+                let size = frame_reader.dword()? as usize;
+                let kind = frame_reader.word()?;
+                let data = frame_reader.take(size.saturating_sub(CHUNK_HEADER_SIZE))?;
+                let mut chunk = Reader::new(data);
+
+                match kind {
+                    CHUNK_LAYER => file.parse_layer(&mut chunk)?,
+                    CHUNK_CEL => file.parse_cel(&mut chunk, frame)?,
+                    CHUNK_PALETTE => file.parse_palette(&mut chunk)?,
+                    CHUNK_OLD_PALETTE => old_palette = parse_old_palette(&mut chunk, false)?,
+                    CHUNK_OLD_PALETTE_64 => old_palette = parse_old_palette(&mut chunk, true)?,
+                    CHUNK_TAGS => warn(
+                        &mut file.warnings,
+                        "Tags are not supported and were ignored",
+                    ),
+                    CHUNK_SLICE => warn(
+                        &mut file.warnings,
+                        "Slices are not supported and were ignored",
+                    ),
+                    CHUNK_TILESET => warn(
+                        &mut file.warnings,
+                        "Tilesets are not supported and were ignored",
+                    ),
+                    CHUNK_EXTERNAL_FILES => warn(
+                        &mut file.warnings,
+                        "External files are not supported and were ignored",
+                    ),
+                    // Data that doesn't change how the sprite looks
+                    CHUNK_CEL_EXTRA | CHUNK_COLOR_PROFILE | CHUNK_MASK | CHUNK_PATH
+                    | CHUNK_USER_DATA => (),
+                    _ => warn(&mut file.warnings, "Unknown chunks were found and ignored"),
+                }
+            }
+        }
+
+        // Old palette chunks are only used by files written before the new
+        // palette chunk existed
+        if file.palette.is_empty() {
+            file.palette = old_palette;
+        }
+
+        Ok(file)
+    }
+
+    fn parse_header(reader: &mut Reader) -> Result<Header> {
+        let mut reader = Reader::new(reader.take(HEADER_SIZE)?);
+        reader.dword()?;
+        if reader.word()? != FILE_MAGIC {
+            return Err(Error::InvalidAseprite("not an Aseprite file"));
+        }
+
+        let frames = reader.word()?;
+        let width = reader.word()?;
+        let height = reader.word()?;
+        let depth = reader.word()?;
+        if ![8, 16, 32].contains(&depth) {
+            return Err(Error::InvalidAseprite("unsupported color depth"));
+        }
+        let flags = reader.dword()?;
+        // Speed (deprecated) and two reserved dwords
+        reader.take(10)?;
+        let transparent_index = reader.byte()?;
+
+        Ok(Header {
+            frames,
+            width,
+            height,
+            depth,
+            flags,
+            transparent_index,
+        })
+    }
+
+    fn parse_layer(&mut self, chunk: &mut Reader) -> Result<()> {
+        let flags = chunk.word()?;
+        let kind = chunk.word()?;
+        let child_level = chunk.word()?;
+        // Default width and height (ignored)
+        chunk.take(4)?;
+        let blend_mode = chunk.word()?;
+        let opacity = chunk.byte()?;
+        chunk.take(3)?;
+        let name = chunk.string()?;
+
+        if kind > LAYER_TYPE_TILEMAP {
+            return Err(Error::InvalidAseprite("unknown layer type"));
+        }
+
+        self.layers.push(AseLayer {
+            name,
+            visible: flags & LAYER_FLAG_VISIBLE != 0,
+            background: flags & LAYER_FLAG_BACKGROUND != 0,
+            kind,
+            child_level,
+            blend_mode,
+            opacity,
+        });
+
+        Ok(())
+    }
+
+    fn parse_cel(&mut self, chunk: &mut Reader, frame: usize) -> Result<()> {
+        let layer = chunk.word()? as usize;
+        let x = chunk.short()? as i32;
+        let y = chunk.short()? as i32;
+        let opacity = chunk.byte()?;
+        let kind = chunk.word()?;
+        // Z-index (only affects the order of layers in a single frame) and
+        // reserved bytes
+        chunk.take(7)?;
+
+        let content = match kind {
+            CEL_TYPE_RAW | CEL_TYPE_COMPRESSED => {
+                let (width, height) = (chunk.word()?, chunk.word()?);
+                // Cels can be partly outside of the canvas, but never bigger
+                if width > self.header.width || height > self.header.height {
+                    return Err(Error::InvalidAseprite("cel is larger than the sprite"));
+                }
+                let size = Size::new(width as i32, height as i32);
+                let len = width as u64 * height as u64 * (self.header.depth / 8) as u64;
+                let data = chunk.take(chunk.remaining())?;
+                let data = match kind {
+                    CEL_TYPE_RAW => data.to_vec(),
+                    _ => {
+                        let mut pixels = Vec::new();
+                        ZlibDecoder::new(data)
+                            .take(len)
+                            .read_to_end(&mut pixels)
+                            .map_err(|_| Error::InvalidAseprite("invalid compressed cel"))?;
+                        pixels
+                    }
+                };
+                CelContent::Image(size, data)
+            }
+            CEL_TYPE_LINKED => CelContent::Linked(chunk.word()? as usize),
+            // Tilemap layers are not imported, and they are already reported
+            CEL_TYPE_TILEMAP => return Ok(()),
+            _ => return Err(Error::InvalidAseprite("unknown cel type")),
+        };
+
+        self.cels.push(Cel {
+            layer,
+            frame,
+            x,
+            y,
+            opacity,
+            content,
+        });
+
+        Ok(())
+    }
+
+    fn parse_palette(&mut self, chunk: &mut Reader) -> Result<()> {
+        let size = chunk.dword()? as usize;
+        let first = chunk.dword()? as usize;
+        let last = chunk.dword()? as usize;
+        chunk.take(8)?;
+        if first > last || last >= size {
+            return Err(Error::InvalidAseprite("invalid palette"));
+        }
+        if size > MAX_PALETTE {
+            return Err(Error::InvalidAseprite("palette is too big"));
+        }
+
+        self.palette.resize(size, Color::new(0, 0, 0, 255));
+        for i in first..=last {
+            let flags = chunk.word()?;
+            let rgba = chunk.take(4)?;
+            self.palette[i] = Color::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+            // Has name
+            if flags & 1 != 0 {
+                chunk.string()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert the bytes of a pixel to a color
+    fn color(&self, bytes: &[u8], background: bool) -> Color {
+        match bytes {
+            [r, g, b, a] => Color::new(*r, *g, *b, *a),
+            [v, a] => Color::new(*v, *v, *v, *a),
+            [i] if *i == self.header.transparent_index && !background => Color::new(0, 0, 0, 0),
+            [i] => self
+                .palette
+                .get(*i as usize)
+                .copied()
+                .unwrap_or(Color::new(0, 0, 0, 0)),
+            _ => unreachable!("pixels have 1, 2 or 4 bytes"),
+        }
+    }
+}
+
+fn parse_old_palette(chunk: &mut Reader, six_bits: bool) -> Result<Vec<Color>> {
+    let mut palette = Vec::new();
+
+    for _ in 0..chunk.word()? {
+        let skip = chunk.byte()? as usize;
+        palette.resize(palette.len() + skip, Color::new(0, 0, 0, 255));
+        let count = match chunk.byte()? {
+            0 => 256,
+            n => n as usize,
+        };
+
+        if palette.len() + count > MAX_OLD_PALETTE {
+            return Err(Error::InvalidAseprite("palette is too big"));
+        }
+
+        for rgb in chunk.take(count * 3)?.chunks(3) {
+            let c = |v: u8| match six_bits {
+                true => (v as u16 * 255 / 63) as u8,
+                false => v,
+            };
+            palette.push(Color::new(c(rgb[0]), c(rgb[1]), c(rgb[2]), 255));
+        }
+    }
+
+    Ok(palette)
+}
+
+/// Reads the little-endian values of an Aseprite file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            return Err(Error::InvalidAseprite("unexpected end of file"));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn short(&mut self) -> Result<i16> {
+        Ok(self.word()? as i16)
+    }
+
+    fn dword(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.word()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::{Event, State};
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// Build an Aseprite file with 2x2 frames, given the chunks of each frame
+    fn file(depth: u16, flags: u32, frames: Vec<Vec<(u16, Vec<u8>)>>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(FILE_MAGIC.to_le_bytes());
+        bytes.extend((frames.len() as u16).to_le_bytes());
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend(depth.to_le_bytes());
+        bytes.extend(flags.to_le_bytes());
+        bytes.extend([0; 10]);
+        // Transparent index
+        bytes.push(0);
+        bytes.resize(HEADER_SIZE, 0);
+
+        for chunks in frames {
+            let data: Vec<u8> = chunks
+                .iter()
+                .flat_map(|(kind, data)| {
+                    let size = (data.len() + CHUNK_HEADER_SIZE) as u32;
+                    [&size.to_le_bytes()[..], &kind.to_le_bytes(), data].concat()
+                })
+                .collect();
+            let size = (data.len() + FRAME_HEADER_SIZE) as u32;
+            bytes.extend(size.to_le_bytes());
+            bytes.extend(FRAME_MAGIC.to_le_bytes());
+            bytes.extend((chunks.len() as u16).to_le_bytes());
+            bytes.extend([100, 0, 0, 0]);
+            bytes.extend((chunks.len() as u32).to_le_bytes());
+            bytes.extend(data);
+        }
+
+        let size = bytes.len() as u32;
+        bytes[0..4].copy_from_slice(&size.to_le_bytes());
+
+        bytes
+    }
+
+    fn layer(
+        name: &str,
+        kind: u16,
+        child_level: u16,
+        visible: bool,
+        opacity: u8,
+    ) -> (u16, Vec<u8>) {
+        let mut data = Vec::new();
+        data.extend((visible as u16).to_le_bytes());
+        data.extend(kind.to_le_bytes());
+        data.extend(child_level.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(BLEND_MODE_NORMAL.to_le_bytes());
+        data.push(opacity);
+        data.extend([0; 3]);
+        data.extend((name.len() as u16).to_le_bytes());
+        data.extend(name.as_bytes());
+
+        (CHUNK_LAYER, data)
+    }
+
+    fn cel_header(layer: u16, x: i16, y: i16, opacity: u8, kind: u16) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(layer.to_le_bytes());
+        data.extend(x.to_le_bytes());
+        data.extend(y.to_le_bytes());
+        data.push(opacity);
+        data.extend(kind.to_le_bytes());
+        data.extend([0; 7]);
+
+        data
+    }
+
+    fn cel(layer: u16, pos: (i16, i16), size: (u16, u16), pixels: &[u8]) -> (u16, Vec<u8>) {
+        let mut data = cel_header(layer, pos.0, pos.1, 255, CEL_TYPE_RAW);
+        data.extend(size.0.to_le_bytes());
+        data.extend(size.1.to_le_bytes());
+        data.extend(pixels);
+
+        (CHUNK_CEL, data)
+    }
+
+    fn compressed_cel(layer: u16, opacity: u8, size: (u16, u16), pixels: &[u8]) -> (u16, Vec<u8>) {
+        let mut data = cel_header(layer, 0, 0, opacity, CEL_TYPE_COMPRESSED);
+        data.extend(size.0.to_le_bytes());
+        data.extend(size.1.to_le_bytes());
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(pixels).unwrap();
+        data.extend(encoder.finish().unwrap());
+
+        (CHUNK_CEL, data)
+    }
+
+    fn linked_cel(layer: u16, frame: u16) -> (u16, Vec<u8>) {
+        let mut data = cel_header(layer, 0, 0, 255, CEL_TYPE_LINKED);
+        data.extend(frame.to_le_bytes());
+
+        (CHUNK_CEL, data)
+    }
+
+    fn palette(colors: &[[u8; 4]]) -> (u16, Vec<u8>) {
+        let mut data = Vec::new();
+        data.extend((colors.len() as u32).to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend((colors.len() as u32 - 1).to_le_bytes());
+        data.extend([0; 8]);
+        for (i, color) in colors.iter().enumerate() {
+            // Give the first color a name
+            data.extend(((i == 0) as u16).to_le_bytes());
+            data.extend(color);
+            if i == 0 {
+                data.extend(4u16.to_le_bytes());
+                data.extend(b"none");
+            }
+        }
+
+        (CHUNK_PALETTE, data)
+    }
+
+    fn pixel(sprite: &Sprite<TestImage>, layer: usize, x: i32, y: i32) -> [u8; 4] {
+        sprite
+            .layers
+            .canvas_at(layer)
+            .pixel(Point::new(x, y))
+            .into()
+    }
+
+    #[test]
+    fn rgba_layers() {
+        let bytes = file(
+            32,
+            FLAG_LAYER_OPACITY,
+            vec![vec![
+                layer("bottom", LAYER_TYPE_NORMAL, 0, true, 255),
+                layer("top", LAYER_TYPE_NORMAL, 0, false, 128),
+                cel(0, (1, 1), (1, 1), &RED),
+                compressed_cel(1, 255, (2, 1), &[BLUE, BLUE].concat()),
+                palette(&[RED, BLUE]),
+            ]],
+        );
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(sprite.layers.count(), 2);
+        assert_eq!(sprite.layers.canvas_at(0).size(), Size::new(2, 2));
+        assert_eq!(pixel(&sprite, 0, 1, 1), RED);
+        assert_eq!(pixel(&sprite, 0, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&sprite, 1, 1, 0), BLUE);
+        assert!(sprite.layers.get(0).visible());
+        assert!(!sprite.layers.get(1).visible());
+        assert_eq!(sprite.layers.get(1).opacity(), 128);
+        assert_eq!(sprite.palette.colors(), [RED.into(), BLUE.into()]);
+        assert_eq!(sprite.spritesheet, Size::new(1, 1));
+        assert!(sprite.warnings.is_empty());
+    }
+
+    #[test]
+    fn frames_as_spritesheet() {
+        let bytes = file(
+            32,
+            0,
+            vec![
+                vec![
+                    layer("layer", LAYER_TYPE_NORMAL, 0, true, 100),
+                    // Partly out of the frame
+                    cel(0, (1, 0), (2, 1), &[RED, RED].concat()),
+                ],
+                vec![linked_cel(0, 0)],
+                vec![compressed_cel(0, 51, (1, 1), &BLUE)],
+            ],
+        );
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(sprite.spritesheet, Size::new(3, 1));
+        assert_eq!(sprite.layers.canvas_at(0).size(), Size::new(6, 2));
+        // Layer opacity is ignored without the header flag
+        assert_eq!(sprite.layers.get(0).opacity(), 255);
+        assert_eq!(pixel(&sprite, 0, 1, 0), RED);
+        assert_eq!(pixel(&sprite, 0, 2, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&sprite, 0, 3, 0), RED);
+        assert_eq!(pixel(&sprite, 0, 4, 0), [0, 0, 255, 51]);
+    }
+
+    #[test]
+    fn indexed_and_grayscale() {
+        let bytes = file(
+            8,
+            0,
+            vec![vec![
+                layer("layer", LAYER_TYPE_NORMAL, 0, true, 255),
+                cel(0, (0, 0), (2, 1), &[0, 1]),
+                palette(&[RED, BLUE]),
+            ]],
+        );
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        // Index 0 is the transparent index
+        assert_eq!(pixel(&sprite, 0, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(&sprite, 0, 1, 0), BLUE);
+
+        let bytes = file(
+            16,
+            0,
+            vec![vec![
+                layer("layer", LAYER_TYPE_NORMAL, 0, true, 255),
+                cel(0, (0, 0), (1, 1), &[80, 255]),
+            ]],
+        );
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(pixel(&sprite, 0, 0, 0), [80, 80, 80, 255]);
+        assert_eq!(sprite.palette, Palette::default());
+    }
+
+    #[test]
+    fn unsupported_parts_are_warnings() {
+        let bytes = file(
+            32,
+            0,
+            vec![vec![
+                layer("group", LAYER_TYPE_GROUP, 0, false, 255),
+                layer("child", LAYER_TYPE_NORMAL, 1, true, 255),
+                layer("tiles", LAYER_TYPE_TILEMAP, 0, true, 255),
+                layer("top", LAYER_TYPE_NORMAL, 0, true, 255),
+                cel(1, (0, 0), (1, 1), &RED),
+                (CHUNK_CEL, cel_header(2, 0, 0, 255, CEL_TYPE_TILEMAP)),
+                cel(3, (0, 0), (1, 1), &BLUE),
+                (CHUNK_TILESET, vec![0; 8]),
+                (CHUNK_TAGS, vec![0; 8]),
+            ]],
+        );
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(sprite.layers.count(), 2);
+        // Hidden because its group is hidden
+        assert!(!sprite.layers.get(0).visible());
+        assert_eq!(pixel(&sprite, 0, 0, 0), RED);
+        assert_eq!(pixel(&sprite, 1, 0, 0), BLUE);
+        assert_eq!(sprite.warnings.len(), 4);
+        assert!(sprite.warnings.iter().any(|w| w.contains("\"tiles\"")));
+    }
+
+    #[test]
+    fn import_event() {
+        let bytes = file(
+            32,
+            0,
+            vec![
+                vec![
+                    layer("layer", LAYER_TYPE_NORMAL, 0, true, 255),
+                    cel(0, (0, 0), (1, 1), &RED),
+                    (CHUNK_SLICE, vec![0; 8]),
+                ],
+                vec![],
+            ],
+        );
+        let path = std::env::temp_dir().join(format!(
+            "lapix-{}-import_event.aseprite",
+            std::process::id()
+        ));
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut state = State::<TestImage>::new((8, 8).into(), None, None);
+        let result = state.execute(Event::ImportAseprite(path.clone()));
+        std::fs::remove_file(path).unwrap();
+        result.unwrap();
+
+        assert_eq!(state.canvas().size(), Size::new(4, 2));
+        assert_eq!(state.spritesheet(), Size::new(2, 1));
+        assert_eq!(state.canvas().pixel(Point::ZERO), RED.into());
+        assert_eq!(state.take_warnings().len(), 1);
+        assert!(state.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(matches!(
+            read::<TestImage>(b"not an aseprite file"),
+            Err(Error::InvalidAseprite(_))
+        ));
+
+        let mut bytes = file(32, 0, vec![vec![layer("layer", 0, 0, true, 255)]]);
+        bytes[4] = 0;
+        assert!(matches!(
+            read::<TestImage>(&bytes),
+            Err(Error::InvalidAseprite("not an Aseprite file"))
+        ));
+
+        let mut bytes = file(32, 0, vec![vec![cel(0, (0, 0), (2, 2), &RED)]]);
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            read::<TestImage>(&bytes),
+            Err(Error::InvalidAseprite("unexpected end of file"))
+        ));
+    }
+
+    #[test]
+    fn oversized_parts() {
+        let layer = || layer("layer", LAYER_TYPE_NORMAL, 0, true, 255);
+
+        // 65535x65535 frames
+        let mut bytes = file(32, 0, vec![vec![layer()]]);
+        bytes[8..12].copy_from_slice(&[0xff; 4]);
+        assert!(matches!(
+            read::<TestImage>(&bytes),
+            Err(Error::InvalidAseprite("the sprite is too big"))
+        ));
+
+        let bytes = file(32, 0, vec![vec![layer(), cel(0, (0, 0), (3, 1), &[0; 12])]]);
+        assert!(matches!(
+            read::<TestImage>(&bytes),
+            Err(Error::InvalidAseprite("cel is larger than the sprite"))
+        ));
+
+        // A compressed cel with more data than its size is cut short
+        let pixels: Vec<u8> = RED.iter().cycle().take(64).copied().collect();
+        let bytes = file(
+            32,
+            0,
+            vec![vec![layer(), compressed_cel(0, 255, (2, 2), &pixels)]],
+        );
+        assert_eq!(pixel(&read::<TestImage>(&bytes).unwrap(), 0, 1, 1), RED);
+
+        let (kind, mut data) = palette(&[RED]);
+        data[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let bytes = file(32, 0, vec![vec![layer(), (kind, data)]]);
+        assert!(matches!(
+            read::<TestImage>(&bytes),
+            Err(Error::InvalidAseprite("palette is too big"))
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+use super::*;
+use crate::{Bitmap, Color, Error, Layer, Point, Rect, Result, State};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+const LAYER_FLAG_EDITABLE: u16 = 2;
+/// Duration of each frame, in milliseconds
+const FRAME_DURATION: u16 = 100;
+
+/// Write the contents of a [`State`] as an Aseprite file (in RGBA mode). Each
+/// layer becomes an Aseprite layer, and each frame of the spritesheet (from
+/// left to right, then top to bottom) becomes an Aseprite frame.
+pub fn write<IMG>(state: &State<IMG>) -> Result<Vec<u8>>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let layers = state.layers();
+    let spritesheet = state.spritesheet();
+    let canvas_size = layers.canvas_at(0).size();
+    let frame_size = Size::new(
+        canvas_size.x / spritesheet.x as i32,
+        canvas_size.y / spritesheet.y as i32,
+    );
+    if frame_size.x > u16::MAX as i32 || frame_size.y > u16::MAX as i32 {
+        return Err(Error::InvalidAseprite("the frames are too big"));
+    }
+
+    let mut frames = Vec::new();
+    for row in 0..spritesheet.y as i32 {
+        for col in 0..spritesheet.x as i32 {
+            let mut chunks = Vec::new();
+
+            // The first frame also has the data that applies to all frames
+            if frames.is_empty() {
+                if !state.palette().is_empty() {
+                    chunks.push(palette_chunk(state.palette()));
+                }
+                for i in 0..layers.count() {
+                    chunks.push(layer_chunk(layers.get(i), i));
+                }
+            }
+
+            let origin = Point::new(col * frame_size.x, row * frame_size.y);
+            for i in 0..layers.count() {
+                let frame = Rect::new(origin.x, origin.y, frame_size.x, frame_size.y);
+                let img = layers.canvas_at(i).inner();
+                if let Some(chunk) = cel_chunk(img, i, frame)? {
+                    chunks.push(chunk);
+                }
+            }
+
+            frames.push(chunks);
+        }
+    }
+
+    let mut bytes = Writer::new();
+    // File size, written at the end
+    bytes.dword(0);
+    bytes.word(FILE_MAGIC);
+    bytes.word(frames.len() as u16);
+    bytes.word(frame_size.x as u16);
+    bytes.word(frame_size.y as u16);
+    // Color depth
+    bytes.word(32);
+    bytes.dword(FLAG_LAYER_OPACITY);
+    // Speed (deprecated) and reserved dwords
+    bytes.bytes(&[0; 10]);
+    // Transparent index (only for indexed sprites) and reserved bytes
+    bytes.bytes(&[0; 4]);
+    bytes.word(state.palette().len().min(u16::MAX as usize) as u16);
+    // Pixel ratio
+    bytes.bytes(&[1, 1]);
+    bytes.0.resize(HEADER_SIZE, 0);
+
+    for chunks in frames {
+        let size: usize = chunks
+            .iter()
+            .map(|c| c.data.len() + CHUNK_HEADER_SIZE)
+            .sum();
+        bytes.dword((size + FRAME_HEADER_SIZE) as u32);
+        bytes.word(FRAME_MAGIC);
+        bytes.word(chunks.len().min(u16::MAX as usize) as u16);
+        bytes.word(FRAME_DURATION);
+        bytes.bytes(&[0; 2]);
+        bytes.dword(chunks.len() as u32);
+
+        for chunk in chunks {
+            bytes.dword((chunk.data.len() + CHUNK_HEADER_SIZE) as u32);
+            bytes.word(chunk.kind);
+            bytes.bytes(&chunk.data);
+        }
+    }
+
+    let mut bytes = bytes.0;
+    let size = bytes.len() as u32;
+    bytes[0..4].copy_from_slice(&size.to_le_bytes());
+
+    Ok(bytes)
+}
+
+struct Chunk {
+    kind: u16,
+    data: Vec<u8>,
+}
+
+fn palette_chunk(palette: &[Color]) -> Chunk {
+    let mut data = Writer::new();
+    data.dword(palette.len() as u32);
+    // First and last index changed
+    data.dword(0);
+    data.dword(palette.len() as u32 - 1);
+    data.bytes(&[0; 8]);
+
+    for color in palette {
+        // No name
+        data.word(0);
+        data.bytes(&[color.r, color.g, color.b, color.a]);
+    }
+
+    Chunk {
+        kind: CHUNK_PALETTE,
+        data: data.0,
+    }
+}
+
+fn layer_chunk<IMG>(layer: &Layer<IMG>, index: usize) -> Chunk
+where
+    IMG: Bitmap,
+{
+    let mut flags = LAYER_FLAG_EDITABLE;
+    if layer.visible() {
+        flags |= LAYER_FLAG_VISIBLE;
+    }
+
+    let mut data = Writer::new();
+    data.word(flags);
+    data.word(LAYER_TYPE_NORMAL);
+    // Child level, default width and height, and blend mode (normal)
+    data.bytes(&[0; 8]);
+    data.0.push(layer.opacity());
+    data.bytes(&[0; 3]);
+    data.string(&format!("Layer {}", index + 1));
+
+    Chunk {
+        kind: CHUNK_LAYER,
+        data: data.0,
+    }
+}
+
+/// Create a compressed cel with the part of a frame that is not transparent,
+/// if there is any
+fn cel_chunk<IMG: Bitmap>(img: &IMG, layer: usize, frame: Rect<i32>) -> Result<Option<Chunk>> {
+    let points = (0..frame.h)
+        .flat_map(|y| (0..frame.w).map(move |x| Point::new(x, y)))
+        .filter(|&p| img.pixel(frame.pos() + p).a > 0);
+
+    let (mut min, mut max) = (Point::new(frame.w, frame.h), Point::new(-1, -1));
+    for p in points {
+        min = Point::new(min.x.min(p.x), min.y.min(p.y));
+        max = Point::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    if max.x < 0 {
+        return Ok(None);
+    }
+
+    let mut pixels = ZlibEncoder::new(Vec::new(), Compression::default());
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let color = img.pixel(frame.pos() + Point::new(x, y));
+            pixels.write_all(&[color.r, color.g, color.b, color.a])?;
+        }
+    }
+
+    let mut data = Writer::new();
+    data.word(layer as u16);
+    data.word(min.x as u16);
+    data.word(min.y as u16);
+    // Opacity (the opacity of the layer is kept in the layer)
+    data.0.push(u8::MAX);
+    data.word(CEL_TYPE_COMPRESSED);
+    // Z-index and reserved bytes
+    data.bytes(&[0; 7]);
+    data.word((max.x - min.x + 1) as u16);
+    data.word((max.y - min.y + 1) as u16);
+    data.bytes(&pixels.finish()?);
+
+    Ok(Some(Chunk {
+        kind: CHUNK_CEL,
+        data: data.0,
+    }))
+}
+
+/// Writes little-endian values of an Aseprite file
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn word(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn dword(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.word(value.len() as u16);
+        self.bytes(value.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::color::{BLACK, TRANSPARENT};
+    use crate::Event;
+
+    fn draw(state: &mut State<TestImage>, p: Point<i32>) {
+        state.execute(Event::BrushStart).unwrap();
+        state.execute(Event::BrushStroke(p)).unwrap();
+        state.execute(Event::BrushEnd).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut state = State::<TestImage>::new((4, 2).into(), None, None);
+        state.execute(Event::SetSpritesheet((2, 1).into())).unwrap();
+        draw(&mut state, Point::new(1, 1));
+        state.execute(Event::NewLayerAbove).unwrap();
+        state.execute(Event::SwitchLayer(1)).unwrap();
+        draw(&mut state, Point::new(3, 0));
+        state.execute(Event::ChangeLayerOpacity(1, 100)).unwrap();
+        state
+            .execute(Event::ChangeLayerVisibility(1, false))
+            .unwrap();
+
+        let bytes = write(&state).unwrap();
+        let sprite = read::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(sprite.spritesheet, Size::new(2, 1));
+        assert_eq!(sprite.layers.count(), 2);
+        assert_eq!(sprite.layers.canvas_at(0).size(), Size::new(4, 2));
+        assert_eq!(sprite.layers.canvas_at(0).pixel(Point::new(1, 1)), BLACK);
+        assert_eq!(sprite.layers.canvas_at(1).pixel(Point::new(3, 0)), BLACK);
+        assert_eq!(
+            sprite.layers.canvas_at(1).pixel(Point::new(1, 1)),
+            TRANSPARENT
+        );
+        assert!(sprite.layers.get(0).visible());
+        assert!(!sprite.layers.get(1).visible());
+        assert_eq!(sprite.layers.get(1).opacity(), 100);
+        assert_eq!(sprite.palette.colors(), state.palette());
+        assert!(sprite.warnings.is_empty());
+    }
+
+    #[test]
+    fn cels_are_cropped() {
+        let mut img = TestImage::new((4, 4).into(), TRANSPARENT);
+        img.set_pixel(Point::new(1, 2), BLACK);
+        img.set_pixel(Point::new(2, 3), BLACK);
+
+        let chunk = cel_chunk(&img, 0, Rect::new(0, 0, 4, 4)).unwrap().unwrap();
+        // Position and size of the cel
+        assert_eq!(chunk.data[2..6], [1, 0, 2, 0]);
+        assert_eq!(chunk.data[16..20], [2, 0, 2, 0]);
+
+        assert!(cel_chunk(&img, 0, Rect::new(0, 0, 4, 2)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,221 @@
+//! Metadata of the frames of a spritesheet (an atlas), in the JSON format used
+//! by Aseprite and TexturePacker, so that game engines can find each frame in
+//! the exported image
+
+use crate::{Result, Size};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How the frames are listed in the JSON file of an atlas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AtlasLayout {
+    /// An object whose keys are the names of the frames
+    #[default]
+    Hash,
+    /// An array of frames, each with a `filename` field
+    Array,
+}
+
+#[derive(Serialize)]
+struct Atlas {
+    frames: Frames,
+    meta: Meta,
+}
+
+struct Frames {
+    layout: AtlasLayout,
+    /// Name and data of each frame
+    frames: Vec<(String, FrameData)>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameData {
+    frame: AtlasRect,
+    rotated: bool,
+    trimmed: bool,
+    sprite_source_size: AtlasRect,
+    source_size: AtlasSize,
+    /// In milliseconds
+    duration: u16,
+}
+
+#[derive(Serialize)]
+struct ArrayFrame<'a> {
+    filename: &'a str,
+    #[serde(flatten)]
+    data: &'a FrameData,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    app: &'static str,
+    version: &'static str,
+    image: String,
+    format: &'static str,
+    size: AtlasSize,
+    scale: &'static str,
+    /// Tarsila has no tags, but engines usually expect the field
+    frame_tags: Vec<()>,
+}
+
+#[derive(Serialize)]
+struct AtlasRect {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+#[derive(Serialize)]
+struct AtlasSize {
+    w: i32,
+    h: i32,
+}
+
+impl Serialize for Frames {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        // The keys of a map are kept in the order of the frames
+        match self.layout {
+            AtlasLayout::Hash => {
+                serializer.collect_map(self.frames.iter().map(|(name, data)| (name, data)))
+            }
+            AtlasLayout::Array => {
+                serializer.collect_seq(self.frames.iter().map(|(name, data)| ArrayFrame {
+                    filename: name,
+                    data,
+                }))
+            }
+        }
+    }
+}
+
+/// Get the JSON of the atlas of a spritesheet image saved at `image_path`.
+/// Frames go from left to right, then top to bottom, and are named after the
+/// image and their index (e.g. `walk 0.png`, `walk 1.png`, etc., for
+/// `walk.png`), like Aseprite does.
+pub(crate) fn to_json(
+    image_path: &Path,
+    image_size: Size<i32>,
+    spritesheet: Size<u8>,
+    layout: AtlasLayout,
+    frame_delay: u16,
+) -> Result<Vec<u8>> {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = image_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let (w, h) = (
+        image_size.x / spritesheet.x as i32,
+        image_size.y / spritesheet.y as i32,
+    );
+
+    let mut frames = Vec::new();
+    for row in 0..spritesheet.y as i32 {
+        for col in 0..spritesheet.x as i32 {
+            let name = format!("{stem} {}{extension}", frames.len());
+            let data = FrameData {
+                frame: AtlasRect {
+                    x: col * w,
+                    y: row * h,
+                    w,
+                    h,
+                },
+                rotated: false,
+                trimmed: false,
+                sprite_source_size: AtlasRect { x: 0, y: 0, w, h },
+                source_size: AtlasSize { w, h },
+                duration: frame_delay,
+            };
+            frames.push((name, data));
+        }
+    }
+
+    let atlas = Atlas {
+        frames: Frames { layout, frames },
+        meta: Meta {
+            app: env!("CARGO_PKG_HOMEPAGE"),
+            version: env!("CARGO_PKG_VERSION"),
+            image: image_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            format: "RGBA8888",
+            size: AtlasSize {
+                w: image_size.x,
+                h: image_size.y,
+            },
+            scale: "1",
+            frame_tags: Vec::new(),
+        },
+    };
+
+    Ok(serde_json::to_vec_pretty(&atlas)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn atlas(layout: AtlasLayout) -> Value {
+        let json = to_json(
+            Path::new("out/walk.png"),
+            (6, 4).into(),
+            (3, 2).into(),
+            layout,
+            80,
+        );
+        serde_json::from_slice(&json.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hash_layout() {
+        let atlas = atlas(AtlasLayout::Hash);
+
+        let frames = atlas["frames"].as_object().unwrap();
+        assert_eq!(frames.len(), 6);
+        assert_eq!(
+            atlas["frames"]["walk 4.png"],
+            json!({
+                "frame": { "x": 2, "y": 2, "w": 2, "h": 2 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 2, "h": 2 },
+                "sourceSize": { "w": 2, "h": 2 },
+                "duration": 80
+            })
+        );
+        assert_eq!(atlas["meta"]["image"], "walk.png");
+        assert_eq!(atlas["meta"]["size"], json!({ "w": 6, "h": 4 }));
+        assert_eq!(atlas["meta"]["frameTags"], json!([]));
+    }
+
+    #[test]
+    fn array_layout() {
+        let atlas = atlas(AtlasLayout::Array);
+
+        let frames = atlas["frames"].as_array().unwrap();
+        let names: Vec<_> = frames.iter().map(|f| f["filename"].clone()).collect();
+        assert_eq!(
+            names,
+            [
+                "walk 0.png",
+                "walk 1.png",
+                "walk 2.png",
+                "walk 3.png",
+                "walk 4.png",
+                "walk 5.png"
+            ]
+        );
+        assert_eq!(
+            frames[2]["frame"],
+            json!({ "x": 4, "y": 0, "w": 2, "h": 2 })
+        );
+        assert_eq!(frames[2]["duration"], 80);
+    }
+}
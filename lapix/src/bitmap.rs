@@ -1,4 +1,5 @@
 use crate::{color, Color, Error, Point, Result, Size};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 // TODO rename this trait
 /// Represents a 2D matrix of pixels (an image)
@@ -67,6 +68,106 @@ pub trait Bitmap: Clone {
     }
 }
 
+/// A plain RGBA image stored in memory, with no dependency on any graphics
+/// library. Useful for frontends that do not display anything, like
+/// command-line tools. It is serialized as PNG bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    size: Size<i32>,
+    bytes: Vec<u8>,
+}
+
+impl RgbaImage {
+    fn index(&self, p: Point<i32>) -> usize {
+        (p.y as usize * self.size.x as usize + p.x as usize) * 4
+    }
+}
+
+impl Bitmap for RgbaImage {
+    fn new(size: Size<i32>, color: Color) -> Self {
+        let pixels = size.x as usize * size.y as usize;
+        let bytes = [color.r, color.g, color.b, color.a].repeat(pixels);
+
+        Self { size, bytes }
+    }
+
+    fn size(&self) -> Size<i32> {
+        self.size
+    }
+
+    fn width(&self) -> i32 {
+        self.size.x
+    }
+
+    fn height(&self) -> i32 {
+        self.size.y
+    }
+
+    fn pixel(&self, p: Point<i32>) -> Color {
+        let i = self.index(p);
+        Color::new(
+            self.bytes[i],
+            self.bytes[i + 1],
+            self.bytes[i + 2],
+            self.bytes[i + 3],
+        )
+    }
+
+    fn set_pixel(&mut self, p: Point<i32>, color: Color) {
+        let i = self.index(p);
+        self.bytes[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn from_parts(size: Size<i32>, bytes: &[u8]) -> Self {
+        Self {
+            size,
+            bytes: bytes.to_owned(),
+        }
+    }
+
+    fn set_from(&mut self, other: &Self) {
+        let w = std::cmp::min(self.width(), other.width());
+        let h = std::cmp::min(self.height(), other.height());
+
+        for y in 0..h {
+            let start = other.index((0, y).into());
+            let dest = self.index((0, y).into());
+            let len = w as usize * 4;
+            self.bytes[dest..dest + len].copy_from_slice(&other.bytes[start..start + len]);
+        }
+    }
+}
+
+impl Serialize for RgbaImage {
+    fn serialize<S>(&self, ser: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::Error as _;
+        let bytes = self
+            .png_bytes()
+            .map_err(|e| S::Error::custom(format!("Error serializing image: {}", e)))?;
+
+        ser.serialize_bytes(&bytes)
+    }
+}
+
+impl<'a> Deserialize<'a> for RgbaImage {
+    fn deserialize<D>(d: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        use serde::de::Error as _;
+        let vec = Vec::<u8>::deserialize(d)?;
+        Self::try_from_file_bytes(vec)
+            .map_err(|e| D::Error::custom(format!("Error deserializing image: {}", e)))
+    }
+}
+
 #[cfg(feature = "test-utils")]
 pub use test::TestImage;
 
@@ -135,7 +236,20 @@ mod test {
             &self.bytes
         }
         fn from_parts(size: Size<i32>, bytes: &[u8]) -> Self {
-            todo!()
+            let colors: Vec<_> = bytes
+                .chunks(4)
+                .map(|c| Color::new(c[0], c[1], c[2], c[3]))
+                .collect();
+            let pixels = colors
+                .chunks(size.x as usize)
+                .map(|row| row.to_vec())
+                .collect();
+
+            Self {
+                size,
+                pixels,
+                bytes: bytes.to_owned(),
+            }
         }
         fn set_from(&mut self, other: &Self) {
             self.pixels = other.pixels.clone();
@@ -160,6 +274,50 @@ mod test {
                     }
                 }
             }
+
+            self.update_bytes();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{BLACK, TRANSPARENT};
+
+    #[test]
+    fn rgba_image_pixels() {
+        let red = Color::new(255, 0, 0, 255);
+        let mut img = RgbaImage::new(Size::new(3, 2), TRANSPARENT);
+        img.set_pixel((2, 1).into(), red);
+
+        assert_eq!(img.pixel((2, 1).into()), red);
+        assert_eq!(img.pixel((1, 1).into()), TRANSPARENT);
+        assert_eq!(&img.bytes()[20..24], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rgba_image_set_from() {
+        let mut small = RgbaImage::new(Size::new(2, 2), BLACK);
+        let mut big = RgbaImage::new(Size::new(3, 3), TRANSPARENT);
+        small.set_pixel((1, 1).into(), TRANSPARENT);
+        big.set_from(&small);
+
+        assert_eq!(big.pixel((0, 0).into()), BLACK);
+        assert_eq!(big.pixel((1, 1).into()), TRANSPARENT);
+        assert_eq!(big.pixel((2, 2).into()), TRANSPARENT);
+
+        small.set_from(&big);
+        assert_eq!(small.pixel((1, 0).into()), BLACK);
+    }
+
+    #[test]
+    fn rgba_image_serde() {
+        let mut img = RgbaImage::new(Size::new(4, 3), TRANSPARENT);
+        img.set_pixel((3, 2).into(), BLACK);
+
+        let bytes = bincode::serialize(&img).unwrap();
+        let img2: RgbaImage = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(img, img2);
+    }
+}
@@ -1,9 +1,13 @@
 use crate::color::TRANSPARENT;
-use crate::{graphics, Bitmap, Color, FreeImage, Point, Rect, Size};
+use crate::{graphics, Bitmap, BucketOptions, Color, ColorF32, FreeImage, Point, Rect, Size};
 use serde::{Deserialize, Serialize};
 
+/// Distance between transparent black and opaque white, the most different
+/// colors
+const MAX_COLOR_DIST: f32 = 2.;
+
 /// Effects that certain actions can have on the canvas
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CanvasEffect {
     /// Action does not affect the canvas at all
     None,
@@ -13,6 +17,95 @@ pub enum CanvasEffect {
     New,
     /// Action forces the layers to be updated
     Layer,
+    /// Action changed only the pixels in an area of some layers
+    Pixels(DirtyRect),
+}
+
+impl CanvasEffect {
+    /// Combine the effects of two actions into one effect that covers both
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::None, fx) | (fx, Self::None) => fx,
+            (Self::New, _) | (_, Self::New) => Self::New,
+            (Self::Layer, _) | (_, Self::Layer) => Self::Layer,
+            (Self::Update, Self::Update) => Self::Update,
+            (Self::Pixels(a), Self::Pixels(b)) => Self::Pixels(a.merge(b)),
+            // `Update` only refers to the active layer, while the pixels
+            // could be in any layer
+            (Self::Update, Self::Pixels(_)) | (Self::Pixels(_), Self::Update) => Self::Layer,
+        }
+    }
+}
+
+/// The area (bounding rectangle) changed in one or more layers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyRect {
+    /// Indices of the layers affected
+    pub layers: Vec<usize>,
+    /// Area affected, the same for all layers
+    pub rect: Rect<i32>,
+}
+
+impl DirtyRect {
+    /// Create a dirty rectangle for a single layer
+    pub fn new(layer: usize, rect: Rect<i32>) -> Self {
+        Self {
+            layers: vec![layer],
+            rect,
+        }
+    }
+
+    /// Create the dirty rectangle that bounds a set of points of a layer. If
+    /// there are no points, returns `None`
+    pub fn from_points<I>(layer: usize, points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Point<i32>>,
+    {
+        points
+            .into_iter()
+            .map(|p| Rect::new(p.x, p.y, 1, 1))
+            .reduce(Rect::union)
+            .map(|rect| Self::new(layer, rect))
+    }
+
+    /// Combine two dirty rectangles into one that covers both
+    pub fn merge(mut self, other: Self) -> Self {
+        for layer in other.layers {
+            if !self.layers.contains(&layer) {
+                self.layers.push(layer);
+            }
+        }
+        self.rect = self.rect.union(other.rect);
+
+        self
+    }
+}
+
+/// A horizontal run of pixels of the same color, used to record the changes
+/// made by filling large areas
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    /// The leftmost pixel
+    pub start: Point<i32>,
+    /// How many pixels there are
+    pub len: i32,
+    pub color: Color,
+}
+
+impl Span {
+    /// Create a span of `len` pixels starting at `start` and going right
+    pub fn new(start: Point<i32>, len: i32, color: Color) -> Self {
+        Self { start, len, color }
+    }
+
+    /// Get the rectangle that bounds a set of spans. If there are no spans,
+    /// returns `None`
+    pub fn bounds(spans: &[Self]) -> Option<Rect<i32>> {
+        spans
+            .iter()
+            .map(|span| Rect::new(span.start.x, span.start.y, span.len, 1))
+            .reduce(Rect::union)
+    }
 }
 
 /// The canvas is the area where drawing can take place. Each layer has a
@@ -219,71 +312,140 @@ impl<IMG: Bitmap> Canvas<IMG> {
         reversals
     }
 
-    /// Paint an enclosed area with a certain color. Returns a set of reversals
-    /// (points and colors they need to be set to in order to reverse the
-    /// action).
-    pub fn bucket(&mut self, p: Point<i32>, color: Color) -> Vec<(Point<i32>, Color)> {
-        let old_color = self.inner.pixel(p);
+    /// Set the colors of a row of pixels. Returns the spans needed to reverse
+    /// it (one for each run of pixels that had the same color).
+    pub fn set_span(&mut self, span: Span) -> Vec<Span> {
+        let mut reversals = Vec::new();
+        self.paint_span(span, &mut reversals);
+
+        reversals
+    }
+
+    /// Same as [`Canvas::set_span`], but adding the reversals to a list, so
+    /// that painting many short spans doesn't allocate for each of them
+    fn paint_span(&mut self, span: Span, reversals: &mut Vec<Span>) {
+        let first = reversals.len();
+
+        for x in span.start.x..span.start.x + span.len {
+            let p = Point::new(x, span.start.y);
+            let old = self.inner.pixel(p);
+            match reversals[first..].last_mut() {
+                Some(last) if last.color == old => last.len += 1,
+                _ => reversals.push(Span::new(p, 1, old)),
+            }
+            if old != span.color {
+                self.inner.set_pixel(p, span.color);
+            }
+        }
+    }
 
-        if color == old_color {
+    /// Paint an enclosed area with a certain color. Returns a set of reversals
+    /// (spans of pixels and colors they need to be set to in order to reverse
+    /// the action).
+    pub fn bucket(&mut self, p: Point<i32>, color: Color, options: BucketOptions) -> Vec<Span> {
+        // With some tolerance, similar pixels around might still change
+        if color == self.inner.pixel(p) && options.tolerance == 0 {
             return Vec::new();
         }
 
-        let w = self.inner.width() as usize;
-        let h = self.inner.height() as usize;
+        let mut reversals = Vec::new();
+        for (start, len) in self.area(p, options) {
+            self.paint_span(Span::new(start, len, color), &mut reversals);
+        }
 
-        let mut marked = vec![false; w * h];
-        let mut visit = vec![(p.x, p.y)];
+        reversals
+    }
 
-        let mut reversals = Vec::new();
+    /// Get the pixels the bucket fills when clicking on a point, as rows (the
+    /// leftmost pixel and the length of each)
+    pub(crate) fn area(&self, p: Point<i32>, options: BucketOptions) -> Vec<(Point<i32>, i32)> {
+        let old_color = self.inner.pixel(p);
+        if options.replace_all {
+            return self.matching_pixels(old_color, options.tolerance);
+        }
 
-        loop {
-            if visit.is_empty() {
-                break;
+        let w = self.inner.width();
+        let h = self.inner.height();
+        let mut marked = vec![false; (w * h) as usize];
+        let fillable = |marked: &[bool], x: i32, y: i32| {
+            !marked[(y * w + x) as usize]
+                && color_matches(
+                    self.inner.pixel((x, y).into()),
+                    old_color,
+                    options.tolerance,
+                )
+        };
+
+        // Each seed is a pixel that starts a new row, which goes as far as
+        // possible to both sides before looking for seeds in the rows above and
+        // below it
+        let mut seeds = vec![p];
+        let mut rows = Vec::new();
+
+        while let Some(seed) = seeds.pop() {
+            if !fillable(&marked, seed.x, seed.y) {
+                continue;
             }
 
-            let mut new_visit = Vec::new();
-            while let Some((vx, vy)) = visit.pop() {
-                marked[(vy as usize) * w + vx as usize] = true;
+            let (mut start, mut end) = (seed.x, seed.x);
+            while start > 0 && fillable(&marked, start - 1, seed.y) {
+                start -= 1;
+            }
+            while end + 1 < w && fillable(&marked, end + 1, seed.y) {
+                end += 1;
+            }
 
-                if let Some(action) = self.set_pixel((vx, vy).into(), color) {
-                    reversals.push(action);
+            let row = (seed.y * w) as usize;
+            marked[row + start as usize..=row + end as usize].fill(true);
+            rows.push((Point::new(start, seed.y), end - start + 1));
+
+            // Diagonal neighbors touch the corners of the row
+            let (start, end) = match options.diagonal {
+                true => ((start - 1).max(0), (end + 1).min(w - 1)),
+                false => (start, end),
+            };
+            for y in [seed.y - 1, seed.y + 1] {
+                if y < 0 || y >= h {
+                    continue;
                 }
 
-                for (nx, ny) in self.neighbors(vx, vy).into_iter().flatten() {
-                    let ind = (ny as usize) * w + nx as usize;
-                    if self.inner.pixel((nx, ny).into()) == old_color && !marked[ind] {
-                        new_visit.push((nx, ny));
-                        marked[ind] = true;
+                let mut in_row = false;
+                for x in start..=end {
+                    let is_fillable = fillable(&marked, x, y);
+                    if is_fillable && !in_row {
+                        seeds.push((x, y).into());
                     }
+                    in_row = is_fillable;
                 }
             }
-
-            visit.append(&mut new_visit);
         }
 
-        reversals
+        rows
     }
 
-    fn neighbors(&self, x: i32, y: i32) -> [Option<(i32, i32)>; 4] {
-        let mut neighbors = [None; 4];
-        let w = self.inner.width();
-        let h = self.inner.height();
+    /// Get every pixel that matches a color, wherever it is on the canvas, as
+    /// rows
+    fn matching_pixels(&self, color: Color, tolerance: u8) -> Vec<(Point<i32>, i32)> {
+        let mut rows = Vec::new();
+
+        for y in 0..self.inner.height() {
+            let mut x = 0;
+            while x < self.inner.width() {
+                let start = x;
+                while x < self.inner.width()
+                    && color_matches(self.inner.pixel((x, y).into()), color, tolerance)
+                {
+                    x += 1;
+                }
 
-        if x + 1 < w {
-            neighbors[0] = Some((x + 1, y));
-        }
-        if x > 0 {
-            neighbors[1] = Some((x - 1, y));
-        }
-        if y + 1 < h {
-            neighbors[2] = Some((x, y + 1));
-        }
-        if y > 0 {
-            neighbors[3] = Some((x, y - 1));
+                if x > start {
+                    rows.push((Point::new(start, y), x - start));
+                }
+                x += 1;
+            }
         }
 
-        neighbors
+        rows
     }
 
     /// Get an image from a certain area of the canvas (determined by a
@@ -307,13 +469,51 @@ impl<IMG: Bitmap> Canvas<IMG> {
     }
 }
 
+/// Whether a color is close enough to another to be painted by the bucket,
+/// given a tolerance from 0 to 100
+fn color_matches(color: Color, other: Color, tolerance: u8) -> bool {
+    if tolerance == 0 {
+        return color == other;
+    }
+
+    let dist = ColorF32::from(color).dist(&ColorF32::from(other));
+    dist <= MAX_COLOR_DIST * tolerance.min(100) as f32 / 100.
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bitmap::TestImage;
     use crate::color::TRANSPARENT;
+    use crate::RgbaImage;
     use test_case::test_case;
 
+    fn pixels(layer: usize, rect: (i32, i32, i32, i32)) -> CanvasEffect {
+        CanvasEffect::Pixels(DirtyRect::new(layer, rect.into()))
+    }
+
+    #[test_case(CanvasEffect::None, CanvasEffect::Update, CanvasEffect::Update)]
+    #[test_case(CanvasEffect::Layer, CanvasEffect::New, CanvasEffect::New)]
+    #[test_case(CanvasEffect::Update, pixels(0, (0, 0, 1, 1)), CanvasEffect::Layer)]
+    #[test_case(pixels(0, (0, 0, 1, 1)), CanvasEffect::None, pixels(0, (0, 0, 1, 1)))]
+    #[test_case(pixels(0, (0, 0, 1, 1)), pixels(0, (2, 2, 1, 1)), pixels(0, (0, 0, 3, 3)))]
+    fn merge_effects(a: CanvasEffect, b: CanvasEffect, res: CanvasEffect) {
+        assert_eq!(a.merge(b), res);
+    }
+
+    #[test]
+    fn dirty_rect_from_points() {
+        let points = vec![Point::new(3, 1), Point::new(1, 4), Point::new(2, 2)];
+        let dirty = DirtyRect::from_points(1, points).unwrap();
+        assert_eq!(dirty, DirtyRect::new(1, Rect::new(1, 1, 3, 4)));
+
+        let merged = dirty.merge(DirtyRect::new(0, Rect::new(0, 0, 1, 1)));
+        assert_eq!(merged.layers, vec![1, 0]);
+        assert_eq!(merged.rect, Rect::new(0, 0, 4, 5));
+
+        assert_eq!(DirtyRect::from_points(0, Vec::new()), None);
+    }
+
     #[test]
     fn basic_properties() {
         let canvas = Canvas::<TestImage>::new(Size::new(5, 10));
@@ -433,7 +633,7 @@ mod tests {
         canvas.set_pixel(Point::new(1, 2), black);
         canvas.set_pixel(Point::new(2, 2), black);
         canvas.set_pixel(Point::new(3, 1), black);
-        canvas.bucket(Point::new(1, 1), black);
+        canvas.bucket(Point::new(1, 1), black, BucketOptions::default());
         assert_points(
             &canvas,
             &[
@@ -448,4 +648,140 @@ mod tests {
             ],
         );
     }
+
+    /// A 4x3 canvas with a dark gray diagonal line splitting it in two: the
+    /// left part is transparent and the right one is almost black
+    fn split_canvas() -> Canvas<TestImage> {
+        let mut canvas = Canvas::<TestImage>::new(Size::new(4, 3));
+        let gray = Color::new(50, 50, 50, 255);
+        let almost_black = Color::new(10, 10, 10, 255);
+        for x in 0..4 {
+            for y in 0..3 {
+                let color = match x - y {
+                    1 => gray,
+                    2.. => almost_black,
+                    _ => TRANSPARENT,
+                };
+                canvas.set_pixel(Point::new(x, y), color);
+            }
+        }
+
+        canvas
+    }
+
+    // Only the pixels 4-connected to the clicked one with the same color
+    #[test_case(0, false, false, (3, 0), 3)]
+    #[test_case(0, false, false, (0, 0), 6)]
+    #[test_case(0, false, false, (1, 0), 1)]
+    // The gray pixels are close enough to the almost black ones
+    #[test_case(15, false, false, (3, 0), 6)]
+    #[test_case(100, false, false, (0, 0), 12)]
+    // The gray pixels only touch diagonally
+    #[test_case(0, false, true, (1, 0), 3)]
+    // Every pixel with a matching color, even if they don't touch
+    #[test_case(0, true, false, (1, 0), 3)]
+    #[test_case(15, true, false, (1, 0), 6)]
+    fn bucket_options(
+        tolerance: u8,
+        replace_all: bool,
+        diagonal: bool,
+        p: (i32, i32),
+        filled: usize,
+    ) {
+        let mut canvas = split_canvas();
+        let options = BucketOptions {
+            tolerance,
+            replace_all,
+            diagonal,
+        };
+        let white = Color::new(255, 255, 255, 255);
+        canvas.bucket(p.into(), white, options);
+
+        let mut count = 0;
+        for x in 0..4 {
+            for y in 0..3 {
+                if canvas.pixel(Point::new(x, y)) == white {
+                    count += 1;
+                }
+            }
+        }
+        assert_eq!(count, filled);
+    }
+
+    /// A straightforward pixel by pixel fill, to check the scanline fill of
+    /// [`Canvas::bucket`] against
+    fn pixelwise_bucket(
+        canvas: &mut Canvas<RgbaImage>,
+        p: Point<i32>,
+        color: Color,
+        options: BucketOptions,
+    ) {
+        let old_color = canvas.inner.pixel(p);
+        if color == old_color && options.tolerance == 0 {
+            return;
+        }
+
+        let w = canvas.inner.width();
+        let mut marked = vec![false; (w * canvas.inner.height()) as usize];
+        marked[(p.y * w + p.x) as usize] = true;
+        let mut visit = vec![p];
+
+        while let Some(p) = visit.pop() {
+            canvas.set_pixel(p, color);
+
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let n = p + Point::new(dx, dy);
+                    if !(options.diagonal || dx == 0 || dy == 0) || !canvas.is_in_bounds(n) {
+                        continue;
+                    }
+                    let i = (n.y * w + n.x) as usize;
+                    if !marked[i]
+                        && color_matches(canvas.inner.pixel(n), old_color, options.tolerance)
+                    {
+                        marked[i] = true;
+                        visit.push(n);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test_case(false)]
+    #[test_case(true)]
+    fn bucket_same_as_reference(diagonal: bool) {
+        let colors = [TRANSPARENT, Color::new(0, 0, 0, 255)];
+        let size = Size::new(23, 17);
+        let options = BucketOptions {
+            diagonal,
+            ..Default::default()
+        };
+        // Pseudo-random seed for a linear congruential generator
+        let mut seed: u32 = 7;
+
+        for _ in 0..50 {
+            let mut canvas = Canvas::<RgbaImage>::new(size);
+            for x in 0..size.x {
+                for y in 0..size.y {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    let color = colors[(seed >> 16) as usize % 3 / 2];
+                    canvas.set_pixel(Point::new(x, y), color);
+                }
+            }
+            let before = canvas.inner().clone();
+            let mut expected = Canvas::<RgbaImage>::new(size);
+            expected.inner.set_from(&before);
+
+            let p = Point::new((seed % 23) as i32, (seed % 17) as i32);
+            let red = Color::new(255, 0, 0, 255);
+            let reversals = canvas.bucket(p, red, options);
+            pixelwise_bucket(&mut expected, p, red, options);
+            assert_eq!(canvas.inner(), expected.inner());
+
+            for span in reversals {
+                canvas.set_span(span);
+            }
+            assert_eq!(canvas.inner(), &before);
+        }
+    }
 }
@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 pub const TRANSPARENT: Color = Color::new(0, 0, 0, 0);
 /// The color black
 pub const BLACK: Color = Color::new(0, 0, 0, 255);
+/// The color white
+pub const WHITE: Color = Color::new(255, 255, 255, 255);
 
 /// Represents an RGBA color, with component values from 0-255
 #[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
@@ -1,3 +1,5 @@
+use crate::Size;
+use std::path::PathBuf;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -10,6 +12,19 @@ pub enum Error {
     MissingFreeImage,
     #[error("Unsupported image format")]
     UnsupportedImageFormat,
+    #[error("The export scale must be at least 1")]
+    InvalidExportScale,
+    #[error("There is no layer with index {0}")]
+    LayerNotFound(usize),
+    #[error("No frames to import")]
+    NoFrames,
+    #[error("Invalid spritesheet grid: {0} frames in {1} columns")]
+    InvalidFrameGrid(usize, u8),
+    #[error(
+        "All frames must have the same size: {} is {}x{}, but the first frame is {}x{}",
+        .0.display(), .1.x, .1.y, .2.x, .2.y
+    )]
+    FrameSizeMismatch(PathBuf, Size<i32>, Size<i32>),
     #[error("Drawing action has not started")]
     DrawingNotStarted,
     #[error("Image error: {0}")]
@@ -20,4 +35,52 @@ pub enum Error {
     ReversalNotSet,
     #[error("Codec error: {0}")]
     CodecError(#[from] bincode::Error),
+    #[error("Replayed state differs from the original: {0}")]
+    ReplayMismatch(String),
+    #[error("Bug: missing save project function")]
+    MissingSaveProjectFn,
+    #[error("Bug: missing load project function")]
+    MissingLoadProjectFn,
+    #[error("Failed to save project to {}: {1}", .0.display())]
+    SaveProjectFailed(PathBuf, std::io::Error),
+    #[error("Failed to load project from {}: {1}", .0.display())]
+    LoadProjectFailed(PathBuf, std::io::Error),
+    #[error("{} is not a valid project file: {1}", .0.display())]
+    InvalidProjectFile(PathBuf, bincode::Error),
+    #[error("Not a project file: missing or invalid header")]
+    InvalidProjectHeader,
+    #[error("Unknown project format version: {0}")]
+    UnknownProjectVersion(String),
+    #[error(
+        "Project format version {0} is newer than the ones supported (up to {}), \
+        please update to open this file",
+        crate::project::FORMAT_VERSION
+    )]
+    NewerProjectVersion(u32),
+    #[error("Corrupt project file: {0}")]
+    CorruptProject(&'static str),
+    #[error("Project archive error: {0}")]
+    ArchiveError(#[from] zip::result::ZipError),
+    #[error("Invalid project manifest: {0}")]
+    ManifestError(#[from] serde_json::Error),
+    #[error("Invalid Aseprite file: {0}")]
+    InvalidAseprite(&'static str),
+    #[error("There is no built-in palette named {0:?}")]
+    UnknownPalette(String),
+    #[error("The palette is empty")]
+    EmptyPalette,
+    #[error("The palette has more than 256 colors")]
+    PaletteTooBig,
+    #[error("There is no color with index {0} in the palette")]
+    InvalidPaletteIndex(usize),
+    #[error("The color is already in the palette")]
+    DuplicatePaletteColor,
+    #[error("Invalid palette file: {0}")]
+    InvalidPalette(String),
+    #[error("GIF error: {0}")]
+    GifError(#[from] gif::EncodingError),
+    #[error("PNG error: {0}")]
+    PngError(#[from] png::EncodingError),
+    #[error("The frames are too big for an animation")]
+    AnimationTooBig,
 }
@@ -1,4 +1,7 @@
-pub use crate::{Bitmap, CanvasEffect, Color, Point, Position, Size, Tool, Transform};
+pub use crate::{
+    AnimationOptions, AtlasLayout, Bitmap, BucketOptions, CanvasEffect, Color, ColorMode,
+    ExportMode, GradientOptions, Point, Position, Size, Tool, Transform, UndoLimit,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::path::PathBuf;
@@ -31,28 +34,121 @@ pub enum Event {
     SetTool(Tool),
     /// Set the main color used by most tools
     SetMainColor(Color),
+    /// Set the secondary color, where gradients end
+    SetSecondaryColor(Color),
     /// Add a color to the palette, if it is not already there
     AddToPalette(Color),
     /// Remove a color from the palette
     RemoveFromPalette(Color),
     /// Export the image to the defined path
     Save(PathBuf),
+    /// Export the image to the defined path, scaled up by an integer factor:
+    /// each pixel becomes a square of `scale` by `scale` pixels. The `mode`
+    /// defines which layers are exported (by default, the visible ones). If
+    /// `indexed` is set, the image is saved as an indexed PNG with the colors
+    /// of the palette (pixels with other colors get the closest one).
+    Export {
+        path: PathBuf,
+        scale: u8,
+        #[serde(default)]
+        mode: ExportMode,
+        #[serde(default)]
+        indexed: bool,
+    },
+    /// Export the frames of the spritesheet (with all layers blended) as an
+    /// animated GIF to the defined path
+    ExportGif(PathBuf, AnimationOptions),
+    /// Export the frames of the spritesheet (with all layers blended) as an
+    /// animated PNG (APNG) to the defined path
+    ExportApng(PathBuf, AnimationOptions),
+    /// Export each frame of the spritesheet (with all layers blended) as a PNG
+    /// image. The frames are numbered after the name of the defined path, e.g.
+    /// `walk_000.png`, `walk_001.png`, etc., for `walk.png`
+    ExportFrames(PathBuf),
+    /// Export the image (with the visible layers blended) to the defined path,
+    /// along with a JSON file describing each frame of the spritesheet (its
+    /// rectangle, name and duration in milliseconds), in the format used by
+    /// Aseprite and TexturePacker. The JSON file has the same name as the
+    /// image, with a `.json` extension.
+    ExportAtlas {
+        path: PathBuf,
+        layout: AtlasLayout,
+        frame_delay: u16,
+    },
     /// Import an image from the defined path. The image will be loaded as a
     /// free image that can be moved around, and will resize the canvas if it's
     /// too big for it.
     OpenFile(PathBuf),
+    /// Build a spritesheet from images of equal size, one per frame. The images
+    /// are laid out in a grid with the defined number of columns (from left to
+    /// right, then top to bottom), replacing the contents of the active layer.
+    /// If the number of frames is not a multiple of the number of columns, the
+    /// cells after the last frame are left transparent. The canvas is resized
+    /// to fit the grid, which resizes every layer (the other layers keep their
+    /// contents at the top left corner), and the spritesheet is set to its
+    /// number of columns and rows. All of this is undone in a single step.
+    ImportFrames { paths: Vec<PathBuf>, columns: u8 },
     // TODO: these should be UI events, however we need to see what to do
     // when it comes to UNDO
     /// Save the drawing project to the defined file path
     SaveProject(PathBuf),
     /// Load a drawing project from a path
     LoadProject(PathBuf),
-    /// Load a palette from a file path. The file must be an image. The image
-    /// will be read and colors will be added to the palette without repetition,
-    /// until a certain limit of colors is reached.
+    /// Save the drawing project to the defined file path as an archive with
+    /// one PNG image per layer and a JSON manifest
+    SaveProjectArchive(PathBuf),
+    /// Load a drawing project from an archive saved with `SaveProjectArchive`
+    LoadProjectArchive(PathBuf),
+    /// Replace the drawing project with the contents of an Aseprite file
+    /// (`.ase` or `.aseprite`). Its frames are laid out as a spritesheet. Parts
+    /// of the file that are not supported are skipped, and reported as
+    /// warnings (see [`State::take_warnings`]).
+    ///
+    /// [`State::take_warnings`]: crate::State::take_warnings
+    ImportAseprite(PathBuf),
+    /// Save the drawing project to the defined file path as an Aseprite file.
+    /// Each frame of the spritesheet becomes an Aseprite frame
+    ExportAseprite(PathBuf),
+    /// Load a palette from a file path. The format is chosen by the extension:
+    /// GIMP (`.gpl`), JASC (`.pal`), HEX (`.hex`) and Paint.NET (`.txt`)
+    /// palette files keep the order (and names, if any) of their colors. Any
+    /// other file must be an image, which will be read and colors will be added
+    /// to the palette without repetition, until a certain limit of colors is
+    /// reached.
     LoadPalette(PathBuf),
-    /// Apply bucket to a point (fill with color)
+    /// Save the palette to a file path. The format is chosen by the extension,
+    /// like in `LoadPalette`. Images are saved as a strip of one pixel per
+    /// color.
+    SavePalette(PathBuf),
+    /// Replace a color of the palette, by its index. In indexed mode, all the
+    /// pixels with the old color are recolored.
+    EditPaletteColor(usize, Color),
+    /// Switch the color mode. Switching to indexed mode converts every pixel
+    /// to the closest color of the palette (which must not be empty) or to
+    /// transparent, if it's transparent. In indexed mode, changing the palette
+    /// recolors the layers: when a color is replaced, the pixels that use it
+    /// get the new color, and when colors are removed or the palette is
+    /// replaced, pixels get the closest color of the new palette. Switching to
+    /// RGBA mode doesn't change any pixel.
+    SetColorMode(ColorMode),
+    /// Replace the palette with one of the [`BUILTIN_PALETTES`], by its name
+    ///
+    /// [`BUILTIN_PALETTES`]: crate::BUILTIN_PALETTES
+    SetBuiltinPalette(String),
+    /// Apply bucket to a point (fill with color), according to the
+    /// [`BucketOptions`]
     Bucket(Point<i32>),
+    /// Change the settings of the bucket tool
+    SetBucketOptions(BucketOptions),
+    /// Start dragging a gradient from a point
+    GradientStart(Point<i32>),
+    /// Finish dragging a gradient at a point, filling the selection (if there
+    /// is one) or the area the bucket would fill from the start point (with
+    /// the current [`BucketOptions`]), with a dithered gradient according to
+    /// the [`GradientOptions`]
+    GradientEnd(Point<i32>),
+    /// Change the settings of the gradient tool
+    SetGradientOptions(GradientOptions),
     /// Similar to `BrushStart`, but for eraser
     EraseStart,
     /// Similar to `BrushEnd`, but for eraser
@@ -125,6 +221,12 @@ pub enum Event {
     ApplyTransform(Transform),
     /// Undo the last undoable action
     Undo,
+    /// Redo the last undone action. Any new undoable event discards the
+    /// actions that could be redone
+    Redo,
+    /// Set the limits on how much undo history is kept. If the current history
+    /// exceeds them, the oldest actions are dropped right away.
+    SetUndoLimit(UndoLimit),
 }
 
 impl Event {
@@ -143,6 +245,7 @@ impl Event {
             | Self::BrushStroke(_)
             | Self::LineEnd(_)
             | Self::RectEnd(_)
+            | Self::GradientEnd(_)
             | Self::EllipseEnd(_)
             | Self::Bucket(_)
             | Self::MoveStart(_)
@@ -152,13 +255,23 @@ impl Event {
             | Self::FlipVertical
             | Self::ApplyTransform(_)
             | Self::Erase(_) => CanvasEffect::Update,
-            Self::ResizeCanvas(_) | Self::OpenFile(_) => CanvasEffect::New,
+            Self::ResizeCanvas(_) | Self::OpenFile(_) | Self::ImportFrames { .. } => {
+                CanvasEffect::New
+            }
+            // These can recolor the layers in indexed mode
+            Self::LoadPalette(_)
+            | Self::SetBuiltinPalette(_)
+            | Self::RemoveFromPalette(_)
+            | Self::EditPaletteColor(..)
+            | Self::SetColorMode(_) => CanvasEffect::Layer,
             Self::NewLayerAbove
             | Self::NewLayerBelow
             | Self::DeleteLayer(_)
             | Self::MoveLayerDown(_)
             | Self::MoveLayerUp(_)
-            | Self::LoadProject(_) => CanvasEffect::Layer,
+            | Self::LoadProject(_)
+            | Self::LoadProjectArchive(_)
+            | Self::ImportAseprite(_) => CanvasEffect::Layer,
             x if x.triggers_anchoring() => CanvasEffect::Update,
             _ => CanvasEffect::None,
         }
@@ -169,6 +282,7 @@ impl Event {
         matches!(
             self,
             Self::Undo
+                | Self::Redo
                 | Self::NewLayerAbove
                 | Self::NewLayerBelow
                 | Self::DeleteLayer(_)
@@ -187,6 +301,8 @@ impl Event {
                 | Self::LineEnd(_)
                 | Self::RectStart(_)
                 | Self::RectEnd(_)
+                | Self::GradientStart(_)
+                | Self::GradientEnd(_)
                 | Self::EllipseStart(_)
                 | Self::EllipseEnd(_)
         )
@@ -211,6 +327,8 @@ impl Event {
                 | Self::LineEnd(_)
                 | Self::RectStart(_)
                 | Self::RectEnd(_)
+                | Self::GradientStart(_)
+                | Self::GradientEnd(_)
                 | Self::EllipseStart(_)
                 | Self::EllipseEnd(_)
                 | Self::NewLayerAbove
@@ -227,7 +345,11 @@ impl Event {
                 | Self::EndSelection(_)
                 | Self::Paste(_)
                 | Self::LoadPalette(_)
+                | Self::SetBuiltinPalette(_)
+                | Self::EditPaletteColor(..)
+                | Self::SetColorMode(_)
                 | Self::OpenFile(_)
+                | Self::ImportFrames { .. }
                 | Self::MoveLayerDown(_)
                 | Self::MoveLayerUp(_)
                 | Self::ApplyTransform(_)
@@ -260,9 +382,13 @@ impl Event {
             Self::MoveStart(_)
                 | Self::MoveEnd(_)
                 | Self::SetTool(Tool::Move)
+                | Self::SetBucketOptions(_)
+                | Self::SetGradientOptions(_)
+                | Self::SetUndoLimit(_)
                 | Self::Copy
                 | Self::LineEnd(_)
                 | Self::RectEnd(_)
+                | Self::GradientEnd(_)
                 | Self::EllipseEnd(_)
                 | Self::FlipHorizontal
                 | Self::FlipVertical
@@ -0,0 +1,46 @@
+//! Options to export the image of a drawing project
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Which layers are exported, and how
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportMode {
+    /// All the layers blended together, including the invisible ones
+    AllLayers,
+    /// The visible layers blended together, as shown in the canvas
+    #[default]
+    VisibleLayers,
+    /// One file per layer, named after the index of the layer (see
+    /// [`layer_path`])
+    EachLayer,
+    /// The layers with these indices blended together, whether they are
+    /// visible or not
+    Layers(Vec<usize>),
+}
+
+/// Get the path of the file to which a layer is exported with
+/// [`ExportMode::EachLayer`]: the index of the layer is added to the name of
+/// the file, e.g. `hero_layer0.png`, `hero_layer1.png`, etc., for `hero.png`
+pub fn layer_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}_layer{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_layer{index}"),
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("hero.png", 0, "hero_layer0.png")]
+    #[test_case("out/hero.jpg", 12, "out/hero_layer12.jpg")]
+    #[test_case("hero", 1, "hero_layer1")]
+    fn layer_paths(path: &str, index: usize, expected: &str) {
+        assert_eq!(layer_path(Path::new(path), index), PathBuf::from(expected));
+    }
+}
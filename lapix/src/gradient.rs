@@ -0,0 +1,209 @@
+//! Gradients made of ordered dithering patterns instead of smooth blends, so
+//! that they only use a few solid colors, like pixel art usually does
+
+use crate::{Color, Point};
+use serde::{Deserialize, Serialize};
+
+/// How the colors of a gradient change from its start point to its end point
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradientShape {
+    /// Along the line between the points, with stripes perpendicular to it
+    #[default]
+    Linear,
+    /// In circles around the start point
+    Radial,
+}
+
+/// Size of the ordered dithering (Bayer) matrix used to mix two colors. Larger
+/// matrices have more intermediate patterns between the colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dither {
+    Bayer2,
+    #[default]
+    Bayer4,
+    Bayer8,
+}
+
+/// Settings of the gradient tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GradientOptions {
+    pub shape: GradientShape,
+    pub dither: Dither,
+    /// Go through all the colors of the palette, in order, instead of going
+    /// from the main color to the secondary one
+    pub along_palette: bool,
+}
+
+impl Dither {
+    /// Width and height of the matrix
+    pub fn size(&self) -> i32 {
+        match self {
+            Self::Bayer2 => 2,
+            Self::Bayer4 => 4,
+            Self::Bayer8 => 8,
+        }
+    }
+
+    /// Get the threshold of a pixel, between 0 and 1, above which it takes the
+    /// next color of the gradient
+    fn threshold(&self, p: Point<i32>) -> f32 {
+        let n = self.size();
+        let value = bayer(n, p.x.rem_euclid(n), p.y.rem_euclid(n));
+
+        (value as f32 + 0.5) / (n * n) as f32
+    }
+}
+
+/// Get the value of a cell of the Bayer matrix of size `n`, which is built by
+/// repeating the 2x2 matrix inside each quadrant of the matrix of size `n / 2`
+fn bayer(n: i32, x: i32, y: i32) -> i32 {
+    if n == 1 {
+        return 0;
+    }
+
+    let half = n / 2;
+    let quadrant = match (x / half, y / half) {
+        (0, 0) => 0,
+        (1, 1) => 1,
+        (1, 0) => 2,
+        _ => 3,
+    };
+
+    4 * bayer(half, x % half, y % half) + quadrant
+}
+
+/// A dithered gradient through a list of colors
+pub(crate) struct Gradient {
+    colors: Vec<Color>,
+    start: Point<i32>,
+    end: Point<i32>,
+    options: GradientOptions,
+}
+
+impl Gradient {
+    /// Create a gradient that goes through the colors (which must not be
+    /// empty) from `start` to `end`
+    pub fn new(
+        colors: Vec<Color>,
+        start: Point<i32>,
+        end: Point<i32>,
+        options: GradientOptions,
+    ) -> Self {
+        Self {
+            colors,
+            start,
+            end,
+            options,
+        }
+    }
+
+    /// Get how far a pixel is along the gradient, from 0 (start) to 1 (end)
+    fn position(&self, p: Point<i32>) -> f32 {
+        let (dx, dy) = (
+            (self.end.x - self.start.x) as f32,
+            (self.end.y - self.start.y) as f32,
+        );
+        let (vx, vy) = ((p.x - self.start.x) as f32, (p.y - self.start.y) as f32);
+        let len2 = dx * dx + dy * dy;
+        if len2 == 0. {
+            return 0.;
+        }
+
+        let t = match self.options.shape {
+            GradientShape::Linear => (vx * dx + vy * dy) / len2,
+            GradientShape::Radial => ((vx * vx + vy * vy) / len2).sqrt(),
+        };
+
+        t.clamp(0., 1.)
+    }
+
+    /// Get the color of a pixel: between each pair of consecutive colors, the
+    /// dithering pattern has more pixels of the second color the closer it is
+    pub fn color_at(&self, p: Point<i32>) -> Color {
+        let last = self.colors.len() - 1;
+        let t = self.position(p) * last as f32;
+        let i = (t.floor() as usize).min(last);
+
+        if i < last && t - i as f32 > self.options.dither.threshold(p) {
+            self.colors[i + 1]
+        } else {
+            self.colors[i]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn bayer_matrices() {
+        let matrix = |n| -> Vec<i32> {
+            (0..n)
+                .flat_map(|y| (0..n).map(move |x| bayer(n, x, y)))
+                .collect()
+        };
+
+        assert_eq!(matrix(2), [0, 2, 3, 1]);
+        assert_eq!(
+            matrix(4),
+            [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+        let mut values = matrix(8);
+        values.sort_unstable();
+        assert_eq!(values, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test_case(Dither::Bayer2)]
+    #[test_case(Dither::Bayer4)]
+    #[test_case(Dither::Bayer8)]
+    fn linear_gradient(dither: Dither) {
+        let black = Color::new(0, 0, 0, 255);
+        let white = Color::new(255, 255, 255, 255);
+        let options = GradientOptions {
+            dither,
+            ..Default::default()
+        };
+        let gradient = Gradient::new(vec![black, white], (0, 0).into(), (16, 0).into(), options);
+
+        let whites = |x| {
+            (0..8)
+                .filter(|&y| gradient.color_at((x, y).into()) == white)
+                .count()
+        };
+        assert_eq!(whites(0), 0);
+        // Pixels with the same threshold turn white in order
+        for x in 0..16 - dither.size() {
+            for y in 0..8 {
+                if gradient.color_at((x, y).into()) == white {
+                    let next = (x + dither.size(), y).into();
+                    assert_eq!(gradient.color_at(next), white);
+                }
+            }
+        }
+        assert_eq!(whites(16), 8);
+        assert_eq!(whites(20), 8);
+        assert_eq!(whites(8), 4);
+    }
+
+    #[test]
+    fn radial_gradient_along_colors() {
+        let colors = vec![
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 255, 0, 255),
+            Color::new(0, 0, 255, 255),
+        ];
+        let options = GradientOptions {
+            shape: GradientShape::Radial,
+            ..Default::default()
+        };
+        let gradient = Gradient::new(colors.clone(), (5, 5).into(), (5, 15).into(), options);
+
+        assert_eq!(gradient.color_at((5, 5).into()), colors[0]);
+        assert_eq!(gradient.color_at((0, 5).into()), colors[1]);
+        assert_eq!(gradient.color_at((5, 0).into()), colors[1]);
+        assert_eq!(gradient.color_at((15, 5).into()), colors[2]);
+        assert_eq!(gradient.color_at((20, 20).into()), colors[2]);
+    }
+}
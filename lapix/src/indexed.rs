@@ -0,0 +1,151 @@
+//! Indexed color mode, in which every pixel is either transparent or one of
+//! the colors of the palette
+
+use crate::{color, Bitmap, Color, Error, Result, Transform};
+use png::{BitDepth, ColorType};
+use serde::{Deserialize, Serialize};
+
+/// How the colors of the layers are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// Pixels can have any color
+    #[default]
+    Rgba,
+    /// Pixels are either transparent or refer to an entry of the palette, so
+    /// changing an entry of the palette recolors all pixels that use it.
+    ///
+    /// The layers are still RGBA images, limited to the colors of the palette,
+    /// rather than images of palette indices, so that every tool, effect and
+    /// file format works the same in both modes. A pixel refers to the entry
+    /// with its color, so in this mode the palette can't have two entries with
+    /// the same color: repeated entries are merged into the first one.
+    Indexed,
+}
+
+/// Get the index of the palette entry a color refers to: the entry with the
+/// same color or, if there is none, the closest one. Transparent colors don't
+/// refer to any entry. The palette must not be empty.
+pub(crate) fn palette_index(palette: &[Color], color: Color) -> Option<usize> {
+    if color.a == 0 {
+        return None;
+    }
+
+    match palette.iter().position(|&c| c == color) {
+        Some(i) => Some(i),
+        None => Some(Transform::nearest_color(palette, color)),
+    }
+}
+
+/// Get the color of the palette a color is converted to in indexed mode
+pub(crate) fn palette_color(palette: &[Color], color: Color) -> Color {
+    match palette_index(palette, color) {
+        Some(i) => palette[i],
+        None => color::TRANSPARENT,
+    }
+}
+
+/// Encode an image as an indexed PNG, with the colors of the palette (pixels
+/// with other colors are written with the closest color of the palette). If
+/// there are transparent pixels, an entry is added to the end of the palette
+/// for them.
+pub(crate) fn encode_png<IMG: Bitmap>(img: &IMG, palette: &[Color]) -> Result<Vec<u8>> {
+    if palette.is_empty() {
+        return Err(Error::EmptyPalette);
+    }
+
+    let transparent_index = palette.len();
+    let indices: Vec<_> = img
+        .bytes()
+        .chunks(4)
+        .map(|p| {
+            let color = Color::new(p[0], p[1], p[2], p[3]);
+            palette_index(palette, color).unwrap_or(transparent_index)
+        })
+        .collect();
+
+    let mut colors = palette.to_vec();
+    if indices.contains(&transparent_index) {
+        colors.push(color::TRANSPARENT);
+    }
+    if colors.len() > 256 {
+        return Err(Error::PaletteTooBig);
+    }
+
+    let mut plte = Vec::new();
+    let mut trns = Vec::new();
+    for color in &colors {
+        plte.extend_from_slice(&[color.r, color.g, color.b]);
+        trns.push(color.a);
+    }
+    // Entries after the last translucent one are opaque
+    while trns.last() == Some(&u8::MAX) {
+        trns.pop();
+    }
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, img.width() as u32, img.height() as u32);
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_palette(plte);
+    if !trns.is_empty() {
+        encoder.set_trns(trns);
+    }
+
+    let mut writer = encoder.write_header()?;
+    let indices: Vec<_> = indices.into_iter().map(|i| i as u8).collect();
+    writer.write_image_data(&indices)?;
+    writer.finish()?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::{Point, Size};
+
+    #[test]
+    fn palette_indices() {
+        let palette = [Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255)];
+
+        assert_eq!(palette_index(&palette, Color::new(0, 0, 255, 255)), Some(1));
+        assert_eq!(
+            palette_index(&palette, Color::new(200, 0, 10, 255)),
+            Some(0)
+        );
+        assert_eq!(palette_index(&palette, Color::new(0, 0, 255, 0)), None);
+        assert_eq!(
+            palette_color(&palette, Color::new(10, 0, 200, 255)),
+            palette[1]
+        );
+    }
+
+    #[test]
+    fn indexed_png() {
+        let palette = [
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 0, 255, 128),
+            Color::new(0, 255, 0, 255),
+        ];
+        let mut img = TestImage::new(Size::new(3, 1), color::TRANSPARENT);
+        img.set_pixel(Point::new(0, 0), Color::new(0, 250, 0, 255));
+        img.set_pixel(Point::new(1, 0), palette[1]);
+
+        let bytes = encode_png(&img, &palette).unwrap();
+
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.color_type, ColorType::Indexed);
+        assert_eq!(
+            info.palette.as_deref().unwrap(),
+            [255, 0, 0, 0, 0, 255, 0, 255, 0, 0, 0, 0]
+        );
+        assert_eq!(info.trns.as_deref().unwrap(), [255, 128, 255, 0]);
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buf).unwrap();
+        assert_eq!(buf[..3], [2, 1, 3]);
+    }
+}
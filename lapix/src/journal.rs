@@ -0,0 +1,155 @@
+use crate::{Bitmap, Error, Event, Result, Size, State};
+use serde::{Deserialize, Serialize};
+
+/// An [`Event`] accepted by the [`State`], with its position in the sequence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub event: Event,
+}
+
+/// A record of all the [`Event`]s accepted by a [`State`] since it was
+/// created, in order. A journal can be serialized (e.g. to attach to a bug
+/// report) and later replayed to rebuild the same state from scratch.
+///
+/// Events that read files (such as `OpenFile` or `LoadPalette`) can only be
+/// replayed if those files are still available at the same paths. Loading a
+/// project is recorded, but cannot be replayed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    canvas_size: Size<i32>,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Create an empty journal for a state that starts with a canvas of a
+    /// certain size
+    pub fn new(canvas_size: Size<i32>) -> Self {
+        Self {
+            canvas_size,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Size of the canvas the state had when it was created
+    pub fn canvas_size(&self) -> Size<i32> {
+        self.canvas_size
+    }
+
+    /// Get the recorded entries, in order
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Record an event, giving it the next sequence number
+    pub(crate) fn record(&mut self, event: Event) {
+        let seq = self.entries.last().map(|e| e.seq + 1).unwrap_or(0);
+        self.entries.push(JournalEntry { seq, event });
+    }
+
+    /// Rebuild a [`State`] by executing all the recorded events, in order, on
+    /// a new state with an empty canvas. Changes of the undo limit are
+    /// recorded as `SetUndoLimit` events, so the history is trimmed at the same
+    /// points. Events that only write files (`Save`, `Export`, `ExportGif`,
+    /// `ExportApng`, `ExportFrames`, `ExportAtlas`, `SaveProject`,
+    /// `SaveProjectArchive`, `ExportAseprite` and `SavePalette`) are not
+    /// executed.
+    pub fn replay<IMG>(&self) -> Result<State<IMG>>
+    where
+        IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+    {
+        let mut state = State::new(self.canvas_size, None, None);
+
+        for entry in &self.entries {
+            if matches!(
+                entry.event,
+                Event::Save(_)
+                    | Event::Export { .. }
+                    | Event::ExportGif(..)
+                    | Event::ExportApng(..)
+                    | Event::ExportFrames(_)
+                    | Event::ExportAtlas { .. }
+                    | Event::SaveProject(_)
+                    | Event::SaveProjectArchive(_)
+                    | Event::ExportAseprite(_)
+                    | Event::SavePalette(_)
+            ) {
+                continue;
+            }
+            state.execute(entry.event.clone())?;
+        }
+
+        Ok(state)
+    }
+
+    /// Replay this journal and check that the result is bit-identical to a
+    /// certain state (normally the one that recorded the journal). Returns
+    /// [`Error::ReplayMismatch`] describing the first difference found.
+    pub fn verify<IMG>(&self, state: &State<IMG>) -> Result<()>
+    where
+        IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+    {
+        let replayed = self.replay::<IMG>()?;
+
+        match first_difference(state, &replayed) {
+            Some(diff) => Err(Error::ReplayMismatch(diff)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn first_difference<IMG>(a: &State<IMG>, b: &State<IMG>) -> Option<String>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let (la, lb) = (a.layers(), b.layers());
+
+    if la.count() != lb.count() {
+        return Some(format!("layer count {} != {}", la.count(), lb.count()));
+    }
+
+    for i in 0..la.count() {
+        let (x, y) = (la.get(i), lb.get(i));
+
+        if x.canvas().size() != y.canvas().size() {
+            return Some(format!("size of layer {}", i));
+        }
+        if x.canvas().bytes() != y.canvas().bytes() {
+            return Some(format!("pixels of layer {}", i));
+        }
+        if x.visible() != y.visible() || x.opacity() != y.opacity() {
+            return Some(format!("visibility or opacity of layer {}", i));
+        }
+    }
+
+    let free_image = |s: &State<IMG>| s.free_image().map(|f| (f.rect, f.texture.bytes().to_vec()));
+    let clipboard = |s: &State<IMG>| s.clipboard().map(|img| (img.size(), img.bytes().to_vec()));
+
+    let diffs = [
+        (la.active_index() != lb.active_index(), "active layer"),
+        (a.main_color() != b.main_color(), "main color"),
+        (
+            a.secondary_color() != b.secondary_color(),
+            "secondary color",
+        ),
+        (a.full_palette() != b.full_palette(), "palette"),
+        (a.color_mode() != b.color_mode(), "color mode"),
+        (a.spritesheet() != b.spritesheet(), "spritesheet"),
+        (a.selected_tool() != b.selected_tool(), "tool"),
+        (a.bucket_options() != b.bucket_options(), "bucket options"),
+        (
+            a.gradient_options() != b.gradient_options(),
+            "gradient options",
+        ),
+        (a.selection() != b.selection(), "selection"),
+        (free_image(a) != free_image(b), "free image"),
+        (clipboard(a) != clipboard(b), "clipboard"),
+        (a.undo_limit() != b.undo_limit(), "undo limit"),
+        (a.history_len() != b.history_len(), "undo history"),
+    ];
+
+    diffs
+        .into_iter()
+        .find(|(differs, _)| *differs)
+        .map(|(_, what)| what.to_owned())
+}
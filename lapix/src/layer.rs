@@ -18,6 +18,12 @@ impl<IMG: Bitmap> Layers<IMG> {
         }
     }
 
+    /// Creates a set of layers from a list of [`Layer`]s, from bottom to top,
+    /// and the index of the active one
+    pub(crate) fn from_vec(inner: Vec<Layer<IMG>>, active: usize) -> Self {
+        Self { inner, active }
+    }
+
     /// Get the active [`Layer`]
     pub fn active(&self) -> &Layer<IMG> {
         &self.inner[self.active]
@@ -71,6 +77,28 @@ impl<IMG: Bitmap> Layers<IMG> {
         result
     }
 
+    /// Get an image of some [`Layer`]s (by their indices, from bottom to top)
+    /// blended together with their opacity, whether they are visible or not
+    pub fn blended_layers(&self, indices: &[usize]) -> IMG {
+        let size = self.canvas_at(0).size();
+        let mut result = IMG::new(size, TRANSPARENT);
+
+        for i in 0..size.x {
+            for j in 0..size.y {
+                let p = Point::new(i, j);
+                let mut colors = indices.iter().map(|&index| {
+                    self.canvas_at(index)
+                        .pixel(p)
+                        .with_multiplied_alpha(self.get(index).opacity())
+                });
+                let bottom = colors.next().unwrap_or(TRANSPARENT);
+                result.set_pixel(p, colors.fold(bottom, |acc, c| c.blend_over(acc)));
+            }
+        }
+
+        result
+    }
+
     /// Get a mutable reference to a [`Layer`] by its index
     pub fn get_mut(&mut self, index: usize) -> &mut Layer<IMG> {
         &mut self.inner[index]
@@ -264,4 +292,27 @@ mod tests {
 
         assert_eq!(pixel, res.into());
     }
+
+    #[test]
+    fn blended_layers() {
+        let mut layers = get_three_layer_canvas();
+        let red = Color::new(255, 0, 0, 255);
+        let blue = Color::new(0, 0, 255, 255);
+        layers.canvas_at_mut(0).set_pixel(Point::new(0, 0), red);
+        layers.canvas_at_mut(2).set_pixel(Point::new(0, 0), blue);
+        layers.canvas_at_mut(2).set_pixel(Point::new(1, 1), blue);
+        layers.set_visibility(2, false);
+        layers.set_opacity(0, 0);
+
+        let img = layers.blended_layers(&[0, 2]);
+        assert_eq!(img.pixel(Point::new(0, 0)), blue);
+        assert_eq!(img.pixel(Point::new(1, 1)), blue);
+
+        // Opacity of the layer is applied
+        let img = layers.blended_layers(&[0]);
+        assert_eq!(img.pixel(Point::new(0, 0)).a, 0);
+
+        let img = layers.blended_layers(&[]);
+        assert_eq!(img.pixel(Point::new(0, 0)), color::TRANSPARENT);
+    }
 }
@@ -2,33 +2,49 @@
 #![allow(clippy::uninlined_format_args)]
 
 mod action;
+mod animation;
+pub mod aseprite;
+mod atlas;
 mod bitmap;
 mod canvas;
 pub mod color;
 mod error;
 mod event;
+pub mod export;
 mod floating;
+mod gradient;
 pub mod graphics;
+mod indexed;
+mod journal;
 mod layer;
 mod palette;
 pub mod primitives;
+pub mod project;
 mod state;
+mod tile;
 mod tool;
 mod transform;
 mod util;
 
 use action::{Action, AtomicAction};
-pub use bitmap::Bitmap;
-pub use canvas::{Canvas, CanvasEffect};
+pub use animation::AnimationOptions;
+pub use atlas::AtlasLayout;
+pub use bitmap::{Bitmap, RgbaImage};
+pub use canvas::{Canvas, CanvasEffect, DirtyRect, Span};
 pub use color::{Color, ColorF32};
 pub use error::{Error, Result};
 pub use event::Event;
+pub use export::ExportMode;
 pub use floating::FreeImage;
+pub use gradient::{Dither, GradientOptions, GradientShape};
+pub use indexed::ColorMode;
+pub use journal::{Journal, JournalEntry};
 pub use layer::{Layer, Layers};
 use palette::Palette;
+pub use palette::BUILTIN_PALETTES;
 pub use primitives::*;
-pub use state::{Selection, State};
-pub use tool::Tool;
+pub use state::{Selection, State, UndoLimit};
+pub use tool::{BucketOptions, Tool};
 pub use transform::Transform;
 pub use util::{LoadProject, SaveProject};
 
@@ -0,0 +1,122 @@
+//! Well-known palettes that come with lapix, with their colors in the usual
+//! order. Colors are written as `0xRRGGBB`.
+
+use super::Palette;
+use crate::Color;
+
+/// Names of the built-in palettes
+pub const BUILTIN_PALETTES: [&str; 7] = [
+    "PICO-8",
+    "DawnBringer 16",
+    "DawnBringer 32",
+    "NES",
+    "Game Boy",
+    "Endesga 32",
+    "Sweetie 16",
+];
+
+const PICO_8: [u32; 16] = [
+    0x000000, 0x1d2b53, 0x7e2553, 0x008751, 0xab5236, 0x5f574f, 0xc2c3c7, 0xfff1e8, 0xff004d,
+    0xffa300, 0xffec27, 0x00e436, 0x29adff, 0x83769c, 0xff77a8, 0xffccaa,
+];
+
+const DAWNBRINGER_16: [u32; 16] = [
+    0x140c1c, 0x442434, 0x30346d, 0x4e4a4e, 0x854c30, 0x346524, 0xd04648, 0x757161, 0x597dce,
+    0xd27d2c, 0x8595a1, 0x6daa2c, 0xd2aa99, 0x6dc2ca, 0xdad45e, 0xdeeed6,
+];
+
+const DAWNBRINGER_32: [u32; 32] = [
+    0x000000, 0x222034, 0x45283c, 0x663931, 0x8f563b, 0xdf7126, 0xd9a066, 0xeec39a, 0xfbf236,
+    0x99e550, 0x6abe30, 0x37946e, 0x4b692f, 0x524b24, 0x323c39, 0x3f3f74, 0x306082, 0x5b6ee1,
+    0x639bff, 0x5fcde4, 0xcbdbfc, 0xffffff, 0x9badb7, 0x847e87, 0x696a6a, 0x595652, 0x76428a,
+    0xac3232, 0xd95763, 0xd77bba, 0x8f974a, 0x8a6f30,
+];
+
+/// The distinct colors of the NES (2C02) palette, row by row
+const NES: [u32; 55] = [
+    0x7c7c7c, 0x0000fc, 0x0000bc, 0x4428bc, 0x940084, 0xa80020, 0xa81000, 0x881400, 0x503000,
+    0x007800, 0x006800, 0x005800, 0x004058, 0x000000, 0xbcbcbc, 0x0078f8, 0x0058f8, 0x6844fc,
+    0xd800cc, 0xe40058, 0xf83800, 0xe45c10, 0xac7c00, 0x00b800, 0x00a800, 0x00a844, 0x008888,
+    0xf8f8f8, 0x3cbcfc, 0x6888fc, 0x9878f8, 0xf878f8, 0xf85898, 0xf87858, 0xfca044, 0xf8b800,
+    0xb8f818, 0x58d854, 0x58f898, 0x00e8d8, 0x787878, 0xfcfcfc, 0xa4e4fc, 0xb8b8f8, 0xd8b8f8,
+    0xf8b8f8, 0xf8a4c0, 0xf0d0b0, 0xfce0a8, 0xf8d878, 0xd8f878, 0xb8f8b8, 0xb8f8d8, 0x00fcfc,
+    0xf8d8f8,
+];
+
+/// The four shades of green of the original Game Boy, from dark to light
+const GAME_BOY: [u32; 4] = [0x0f380f, 0x306230, 0x8bac0f, 0x9bbc0f];
+
+const ENDESGA_32: [u32; 32] = [
+    0xbe4a2f, 0xd77643, 0xead4aa, 0xe4a672, 0xb86f50, 0x733e39, 0x3e2731, 0xa22633, 0xe43b44,
+    0xf77622, 0xfeae34, 0xfee761, 0x63c74d, 0x3e8948, 0x265c42, 0x193c3e, 0x124e89, 0x0099db,
+    0x2ce8f5, 0xffffff, 0xc0cbdc, 0x8b9bb4, 0x5a6988, 0x3a4466, 0x262b44, 0x181425, 0xff0044,
+    0x68386c, 0xb55088, 0xf6757a, 0xe8b796, 0xc28569,
+];
+
+const SWEETIE_16: [u32; 16] = [
+    0x1a1c2c, 0x5d275d, 0xb13e53, 0xef7d57, 0xffcd75, 0xa7f070, 0x38b764, 0x257179, 0x29366f,
+    0x3b5dc9, 0x41a6f6, 0x73eff7, 0xf4f4f4, 0x94b0c2, 0x566c86, 0x333c57,
+];
+
+impl Palette {
+    /// Get one of the [`BUILTIN_PALETTES`] by its name (ignoring case)
+    pub fn builtin(name: &str) -> Option<Self> {
+        let index = BUILTIN_PALETTES
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(name))?;
+        let colors: &[u32] = match index {
+            0 => &PICO_8,
+            1 => &DAWNBRINGER_16,
+            2 => &DAWNBRINGER_32,
+            3 => &NES,
+            4 => &GAME_BOY,
+            5 => &ENDESGA_32,
+            _ => &SWEETIE_16,
+        };
+
+        let colors = colors
+            .iter()
+            .map(|rgb| {
+                let [_, r, g, b] = rgb.to_be_bytes();
+                Color::new(r, g, b, 255)
+            })
+            .collect();
+
+        Some(Self::new(colors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("PICO-8", 16)]
+    #[test_case("dawnbringer 16", 16)]
+    #[test_case("DawnBringer 32", 32)]
+    #[test_case("NES", 55)]
+    #[test_case("Game Boy", 4)]
+    #[test_case("Endesga 32", 32)]
+    #[test_case("Sweetie 16", 16)]
+    fn builtin_palettes(name: &str, len: usize) {
+        let palette = Palette::builtin(name).unwrap();
+        let colors = palette.colors();
+        assert_eq!(colors.len(), len);
+        assert!(colors.iter().all(|c| c.a == 255));
+        // No repeated colors
+        assert!(colors
+            .iter()
+            .enumerate()
+            .all(|(i, c)| !colors[i + 1..].contains(c)));
+    }
+
+    #[test]
+    fn builtin_colors() {
+        let pico8 = Palette::builtin("PICO-8").unwrap();
+        assert_eq!(pico8.colors()[1], Color::new(0x1d, 0x2b, 0x53, 255));
+        assert!(Palette::builtin("Unknown").is_none());
+        assert!(BUILTIN_PALETTES
+            .iter()
+            .all(|n| Palette::builtin(n).is_some()));
+    }
+}
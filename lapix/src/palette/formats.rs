@@ -0,0 +1,313 @@
+//! Text palette file formats: GIMP (`.gpl`), JASC (`.pal`), Lospec (`.hex`)
+//! and Paint.NET (`.txt`)
+
+use super::Palette;
+use crate::{Color, Error, Result};
+use std::path::Path;
+
+const GPL_HEADER: &str = "GIMP Palette";
+/// Name GIMP gives to colors without a name
+const GPL_UNNAMED: &str = "Untitled";
+const JASC_HEADER: &str = "JASC-PAL";
+const JASC_VERSION: &str = "0100";
+const PAINT_NET_COMMENT: char = ';';
+
+/// A text format for palette files. Only Paint.NET files keep the alpha of the
+/// colors, and only GIMP files keep their names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PaletteFormat {
+    Gpl,
+    JascPal,
+    Hex,
+    PaintNet,
+}
+
+impl PaletteFormat {
+    /// Get the format of a palette file from its extension, if it's one of the
+    /// text formats
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+
+        match ext.as_str() {
+            "gpl" => Some(Self::Gpl),
+            "pal" => Some(Self::JascPal),
+            "hex" => Some(Self::Hex),
+            "txt" => Some(Self::PaintNet),
+            _ => None,
+        }
+    }
+
+    /// Read a palette from the contents of a file in this format, keeping the
+    /// order of the colors
+    pub fn decode(self, text: &str) -> Result<Palette> {
+        let mut palette = Palette::new(Vec::new());
+        let mut lines = text.lines().map(str::trim).enumerate();
+
+        match self {
+            Self::Gpl => {
+                expect_header(&mut lines, GPL_HEADER)?;
+                for (i, line) in lines {
+                    if line.is_empty()
+                        || line.starts_with('#')
+                        || line.starts_with("Name:")
+                        || line.starts_with("Columns:")
+                    {
+                        continue;
+                    }
+
+                    let (color, name) = parse_rgb(line, i)?;
+                    let name = match name {
+                        "" | GPL_UNNAMED => None,
+                        name => Some(name.to_owned()),
+                    };
+                    palette.push_named(color, name);
+                }
+            }
+            Self::JascPal => {
+                expect_header(&mut lines, JASC_HEADER)?;
+                expect_header(&mut lines, JASC_VERSION)?;
+                let count: usize = match lines.next() {
+                    Some((i, count)) => count
+                        .parse()
+                        .map_err(|_| invalid(i + 1, "invalid color count"))?,
+                    None => return Err(invalid(3, "missing color count")),
+                };
+                let mut lines = lines.filter(|(_, line)| !line.is_empty());
+                for _ in 0..count {
+                    let (i, line) = lines
+                        .next()
+                        .ok_or_else(|| Error::InvalidPalette("missing colors".to_owned()))?;
+                    let (color, _) = parse_rgb(line, i)?;
+                    palette.push_named(color, None);
+                }
+            }
+            Self::Hex => {
+                for (i, line) in lines.filter(|(_, line)| !line.is_empty()) {
+                    let hex = line.strip_prefix('#').unwrap_or(line);
+                    let [r, g, b] = parse_hex(hex, i)?;
+                    palette.push_named(Color::new(r, g, b, 255), None);
+                }
+            }
+            Self::PaintNet => {
+                let lines = lines
+                    .filter(|(_, line)| !line.is_empty() && !line.starts_with(PAINT_NET_COMMENT));
+                for (i, line) in lines {
+                    let [a, r, g, b] = parse_hex(line, i)?;
+                    palette.push_named(Color::new(r, g, b, a), None);
+                }
+            }
+        }
+
+        Ok(palette)
+    }
+
+    /// Write a palette in this format
+    pub fn encode(self, palette: &Palette) -> String {
+        let colors = palette.colors();
+        let mut text = String::new();
+
+        match self {
+            Self::Gpl => {
+                text += &format!("{GPL_HEADER}\nName: Tarsila\nColumns: 0\n#\n");
+                for (i, &c) in colors.iter().enumerate() {
+                    let name = palette.name(i).unwrap_or(GPL_UNNAMED);
+                    text += &format!("{:3} {:3} {:3}\t{name}\n", c.r, c.g, c.b);
+                }
+            }
+            Self::JascPal => {
+                text += &format!("{JASC_HEADER}\n{JASC_VERSION}\n{}\n", colors.len());
+                for c in colors {
+                    text += &format!("{} {} {}\n", c.r, c.g, c.b);
+                }
+            }
+            Self::Hex => {
+                for c in colors {
+                    text += &format!("{:02x}{:02x}{:02x}\n", c.r, c.g, c.b);
+                }
+            }
+            Self::PaintNet => {
+                text += &format!("{PAINT_NET_COMMENT}paint.net Palette File\n");
+                text += &format!("{PAINT_NET_COMMENT}Colors: {}\n", colors.len());
+                for c in colors {
+                    text += &format!("{:02X}{:02X}{:02X}{:02X}\n", c.a, c.r, c.g, c.b);
+                }
+            }
+        }
+
+        text
+    }
+}
+
+fn invalid(line: usize, message: &str) -> Error {
+    Error::InvalidPalette(format!("line {line}: {message}"))
+}
+
+fn expect_header<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    header: &str,
+) -> Result<()> {
+    match lines.next() {
+        Some((_, line)) if line == header => Ok(()),
+        Some((i, _)) => Err(invalid(i + 1, &format!("expected \"{header}\""))),
+        None => Err(Error::InvalidPalette("empty file".to_owned())),
+    }
+}
+
+/// Parse a color written as three decimal numbers separated by spaces.
+/// Returns the color and the rest of the line.
+fn parse_rgb(line: &str, index: usize) -> Result<(Color, &str)> {
+    let mut rest = line;
+    let mut rgb = [0; 3];
+
+    for channel in rgb.iter_mut() {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        *channel = rest[..end]
+            .parse()
+            .map_err(|_| invalid(index + 1, "invalid color"))?;
+        rest = &rest[end..];
+    }
+
+    let [r, g, b] = rgb;
+    Ok((Color::new(r, g, b, 255), rest.trim()))
+}
+
+/// Parse a color written as `N` hexadecimal bytes
+fn parse_hex<const N: usize>(hex: &str, index: usize) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return Err(invalid(index + 1, "invalid color"));
+    }
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| invalid(index + 1, "invalid color"))?;
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn palette(alpha: bool) -> Palette {
+        let mut palette = Palette::new(Vec::new());
+        palette.push_named(Color::new(255, 255, 255, 255), None);
+        palette.push_named(Color::new(0, 0, 0, 255), Some("Ink".to_owned()));
+        let a = if alpha { 100 } else { 255 };
+        palette.push_named(Color::new(18, 52, 86, a), Some("Deep sea".to_owned()));
+
+        palette
+    }
+
+    #[test_case(PaletteFormat::Gpl, true)]
+    #[test_case(PaletteFormat::JascPal, false)]
+    #[test_case(PaletteFormat::Hex, false)]
+    #[test_case(PaletteFormat::PaintNet, false)]
+    fn round_trip(format: PaletteFormat, names: bool) {
+        let alpha = format == PaletteFormat::PaintNet;
+        let mut palette = palette(alpha);
+        if !names {
+            palette.names = vec![None; palette.colors.len()];
+        }
+
+        let text = format.encode(&palette);
+        assert_eq!(format.decode(&text).unwrap(), palette);
+    }
+
+    #[test_case(PaletteFormat::Gpl)]
+    #[test_case(PaletteFormat::JascPal)]
+    #[test_case(PaletteFormat::Hex)]
+    #[test_case(PaletteFormat::PaintNet)]
+    fn round_trip_with_duplicates(format: PaletteFormat) {
+        let white = Color::new(255, 255, 255, 255);
+        let black = Color::new(0, 0, 0, 255);
+        let mut palette = Palette::new(Vec::new());
+        for color in [white, black, white, white] {
+            palette.push_named(color, None);
+        }
+
+        let decoded = format.decode(&format.encode(&palette)).unwrap();
+        assert_eq!(decoded.colors(), [white, black, white, white]);
+    }
+
+    #[test]
+    fn round_trip_with_named_duplicates() {
+        let black = Color::new(0, 0, 0, 255);
+        let mut palette = Palette::new(Vec::new());
+        palette.push_named(black, Some("Ink".to_owned()));
+        palette.push_named(black, Some("Shadow".to_owned()));
+        palette.push_named(black, None);
+
+        let text = PaletteFormat::Gpl.encode(&palette);
+        assert_eq!(PaletteFormat::Gpl.decode(&text).unwrap(), palette);
+        assert!(text.contains("\tInk\n"));
+        assert!(text.contains("\tShadow\n"));
+        assert!(text.contains(&format!("\t{GPL_UNNAMED}\n")));
+    }
+
+    #[test]
+    fn read_gpl() {
+        let text = "GIMP Palette\nName: Test\nColumns: 4\n# comment\n\
+            255   0   0\tRed\n  0 128   0 Dark green\n0 0 255\n";
+        let palette = PaletteFormat::Gpl.decode(text).unwrap();
+
+        let green = Color::new(0, 128, 0, 255);
+        assert_eq!(
+            palette.colors(),
+            [
+                Color::new(255, 0, 0, 255),
+                green,
+                Color::new(0, 0, 255, 255)
+            ]
+        );
+        assert_eq!(palette.name(1), Some("Dark green"));
+        assert_eq!(palette.name(2), None);
+    }
+
+    #[test]
+    fn read_jasc() {
+        let text = "JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n4 5 6\r\n";
+        let palette = PaletteFormat::JascPal.decode(text).unwrap();
+        assert_eq!(
+            palette.colors(),
+            [Color::new(1, 2, 3, 255), Color::new(4, 5, 6, 255)]
+        );
+    }
+
+    #[test]
+    fn read_hex_and_paint_net() {
+        let palette = PaletteFormat::Hex.decode("ff0080\n#00FF00\n\n").unwrap();
+        assert_eq!(
+            palette.colors(),
+            [Color::new(255, 0, 128, 255), Color::new(0, 255, 0, 255)]
+        );
+
+        let text = ";paint.net Palette File\n;Colors: 1\n80FF0000\n";
+        let palette = PaletteFormat::PaintNet.decode(text).unwrap();
+        assert_eq!(palette.colors(), [Color::new(255, 0, 0, 128)]);
+    }
+
+    #[test_case(PaletteFormat::Gpl, "JASC-PAL\n0100\n0\n")]
+    #[test_case(PaletteFormat::Gpl, "GIMP Palette\n255 0\n")]
+    #[test_case(PaletteFormat::JascPal, "JASC-PAL\n0100\n3\n1 2 3\n")]
+    #[test_case(PaletteFormat::JascPal, "")]
+    #[test_case(PaletteFormat::Hex, "ff00\n")]
+    #[test_case(PaletteFormat::Hex, "gg0000\n")]
+    #[test_case(PaletteFormat::PaintNet, "FF0000\n")]
+    fn invalid_files(format: PaletteFormat, text: &str) {
+        assert!(matches!(format.decode(text), Err(Error::InvalidPalette(_))));
+    }
+
+    #[test_case("colors.gpl", Some(PaletteFormat::Gpl))]
+    #[test_case("colors.PAL", Some(PaletteFormat::JascPal))]
+    #[test_case("colors.hex", Some(PaletteFormat::Hex))]
+    #[test_case("colors.txt", Some(PaletteFormat::PaintNet))]
+    #[test_case("colors.png", None)]
+    #[test_case("colors", None)]
+    fn format_from_path(path: &str, format: Option<PaletteFormat>) {
+        assert_eq!(PaletteFormat::from_path(Path::new(path)), format);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{util, Bitmap, Color, Error, Result, RgbaImage, Size};
+use serde::{Deserialize, Serialize};
+use std::mem::size_of_val;
+use std::path::Path;
+
+mod builtin;
+mod formats;
+
+pub use builtin::BUILTIN_PALETTES;
+pub(crate) use formats::PaletteFormat;
+
+const MAX_PALETTE: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    colors: Vec<Color>,
+    /// The name of each entry of the palette, as found in palette files that
+    /// have them. Entries with the same color can have different names.
+    names: Vec<Option<String>>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new(vec![
+            Color::new(0, 0, 0, 255),       // BLACK
+            Color::new(255, 255, 255, 255), // WHITE
+            Color::new(255, 0, 0, 255),     // RED
+            Color::new(255, 127, 0, 255),   // RED + YELLOW = ORANGE
+            Color::new(255, 255, 0, 255),   // YELLOW
+            Color::new(127, 255, 0, 255),   // GREEN + YELLOW
+            Color::new(0, 255, 0, 255),     // GREEN
+            Color::new(0, 255, 127, 255),   // GREEN + CYAN
+            Color::new(0, 255, 255, 255),   // CYAN
+            Color::new(0, 127, 255, 255),   // BLUE + CYAN
+            Color::new(0, 0, 255, 255),     // BLUE
+            Color::new(127, 0, 255, 255),   // BLUE + MAGENTA
+            Color::new(255, 0, 255, 255),   // MAGENTA
+            Color::new(255, 0, 127, 255),   // RED + MAGENTA
+        ])
+    }
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Self {
+        Self {
+            names: vec![None; colors.len()],
+            colors,
+        }
+    }
+
+    /// Load a palette from a file. GIMP (`.gpl`), JASC (`.pal`), Lospec
+    /// (`.hex`) and Paint.NET (`.txt`) palette files are read in the order of
+    /// their colors; any other file is read as an image, taking its distinct
+    /// colors (up to a limit)
+    pub fn from_file(path: &str) -> Result<Self> {
+        if let Some(format) = PaletteFormat::from_path(Path::new(path)) {
+            let text = std::fs::read_to_string(path)?;
+            return format.decode(&text);
+        }
+
+        let img = util::load_img_from_file(path)?;
+
+        Ok(Self::from_image(img))
+    }
+
+    /// Save the palette to a file. The format is chosen by the extension, like
+    /// in [`Palette::from_file`]. Images are saved as a strip of one pixel per
+    /// color.
+    pub fn save(&self, path: &str) -> Result<()> {
+        if let Some(format) = PaletteFormat::from_path(Path::new(path)) {
+            std::fs::write(path, format.encode(self))?;
+            return Ok(());
+        }
+
+        if self.colors.is_empty() {
+            return Err(Error::EmptyPalette);
+        }
+
+        let mut img = RgbaImage::new(Size::new(self.colors.len() as i32, 1), Color::default());
+        for (i, &color) in self.colors.iter().enumerate() {
+            img.set_pixel((i as i32, 0).into(), color);
+        }
+
+        util::save_image(img, path)
+    }
+
+    fn from_image(img: image::RgbaImage) -> Self {
+        let mut palette = Vec::new();
+
+        for (_, _, pixel) in img.enumerate_pixels() {
+            let color = Color::new(pixel.0[0], pixel.0[1], pixel.0[2], pixel.0[3]);
+
+            if !palette.contains(&color) {
+                palette.push(color);
+            }
+
+            if palette.len() >= MAX_PALETTE {
+                break;
+            }
+        }
+        let mut palette = Self::new(palette);
+        palette.sort();
+
+        palette
+    }
+
+    pub fn add_color(&mut self, color: Color) {
+        if !self.colors.contains(&color) {
+            self.push_named(color, None);
+        }
+        self.sort();
+    }
+
+    /// Add an entry to the end of the palette, even if its color is already
+    /// there, so that palette files keep all their entries
+    fn push_named(&mut self, color: Color, name: Option<String>) {
+        self.colors.push(color);
+        self.names.push(name);
+    }
+
+    /// Replace the color at an index of the palette, keeping its name
+    pub fn set_color(&mut self, index: usize, color: Color) {
+        self.colors[index] = color;
+    }
+
+    pub fn remove_color(&mut self, color: Color) {
+        let entries = self.colors.iter().zip(std::mem::take(&mut self.names));
+        self.names = entries
+            .filter(|(c, _)| **c != color)
+            .map(|(_, name)| name)
+            .collect();
+        self.colors.retain(|c| *c != color);
+    }
+
+    /// Remove the entries whose color is already in an earlier entry
+    pub(crate) fn dedup(&mut self) {
+        let mut seen = Vec::new();
+        let entries = std::mem::take(&mut self.colors)
+            .into_iter()
+            .zip(std::mem::take(&mut self.names));
+        (self.colors, self.names) = entries
+            .filter(|(color, _)| {
+                let new = !seen.contains(color);
+                seen.push(*color);
+                new
+            })
+            .unzip();
+    }
+
+    /// Get an estimate of the memory taken by the colors and names, in bytes
+    pub(crate) fn memory_size(&self) -> usize {
+        let names: usize = self.names.iter().flatten().map(String::capacity).sum();
+
+        size_of_val(self.colors.as_slice()) + size_of_val(self.names.as_slice()) + names
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Get the name of the entry at an index of the palette, if it has one
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index)?.as_deref()
+    }
+
+    pub fn sort(&mut self) {
+        fn sort_val(color: &Color) -> i32 {
+            (color.hue() as i32) * 1_000_000
+                + (color.saturation() * 10_000.) as i32
+                + (color.value() * 10_000.) as i32
+        }
+        let mut entries: Vec<_> = self
+            .colors
+            .drain(..)
+            .zip(std::mem::take(&mut self.names))
+            .collect();
+        entries.sort_by_key(|(color, _)| sort_val(color));
+        (self.colors, self.names) = entries.into_iter().unzip();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_bytes(bytes: Vec<u8>) -> Palette {
+        let len = bytes.len() as u32 / 4;
+        let img = image::RgbaImage::from_raw(1, len, bytes).unwrap();
+        Palette::from_image(img)
+    }
+
+    #[test]
+    fn create_from_img() {
+        let bytes = vec![0, 0, 0, 255];
+        let palette = from_bytes(bytes);
+        assert!(palette.colors().contains(&Color::new(0, 0, 0, 255)));
+        assert_eq!(palette.colors().len(), 1);
+
+        let bytes = vec![0, 0, 0, 255, 0, 0, 0, 255];
+        let palette = from_bytes(bytes);
+        assert!(palette.colors().contains(&Color::new(0, 0, 0, 255)));
+        assert_eq!(palette.colors().len(), 1);
+
+        let bytes = vec![0, 0, 0, 255, 255, 0, 0, 255];
+        let palette = from_bytes(bytes);
+        assert!(palette.colors().contains(&Color::new(0, 0, 0, 255)));
+        assert!(palette.colors().contains(&Color::new(255, 0, 0, 255)));
+        assert_eq!(palette.colors().len(), 2);
+    }
+
+    #[test]
+    fn add_and_remove_from_default() {
+        let mut palette = Palette::default();
+
+        let dark = Color::new(10, 10, 10, 255);
+        palette.add_color(dark);
+        assert!(palette.colors().contains(&dark));
+
+        palette.remove_color(dark);
+        assert!(!palette.colors().contains(&dark));
+    }
+
+    #[test]
+    fn add_one() {
+        let bytes = vec![0, 0, 0, 255];
+        let mut palette = from_bytes(bytes);
+
+        let color = Color::new(0, 1, 2, 3);
+        palette.add_color(color);
+        assert!(palette.colors().contains(&color));
+        assert_eq!(palette.colors().len(), 2);
+    }
+
+    #[test]
+    fn memory_size_counts_names() {
+        let mut palette = Palette::new(Vec::new());
+        palette.push_named(Color::new(0, 0, 0, 255), None);
+        let unnamed = palette.memory_size();
+
+        palette.names[0] = Some("A long name for black".to_owned());
+        assert!(palette.memory_size() >= unnamed + "A long name for black".len());
+    }
+
+    #[test]
+    fn remove_one() {
+        let bytes = vec![0, 0, 0, 255, 1, 1, 1, 255];
+        let mut palette = from_bytes(bytes);
+
+        let black = Color::new(0, 0, 0, 255);
+        palette.remove_color(black);
+        assert!(!palette.colors().contains(&black));
+        assert_eq!(palette.colors().len(), 1);
+    }
+}
@@ -180,6 +180,21 @@ impl<T: Number> Rect<T> {
 }
 
 impl<T: Number + Ord> Rect<T> {
+    /// Get the smallest rectangle that contains both this and another
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let x1 = (self.x + self.w).max(other.x + other.w);
+        let y1 = (self.y + self.h).max(other.y + other.h);
+
+        Self {
+            x,
+            y,
+            w: x1 - x,
+            h: y1 - y,
+        }
+    }
+
     /// Clamp the rectangle to the bounds of another (intersection)
     pub fn clip_to(self, other: Self) -> Self {
         let x = self.x.clamp(other.x, other.x + other.w);
@@ -269,6 +284,13 @@ mod tests {
         assert_eq!(r.into().clip_to(clip.into()), res.into());
     }
 
+    #[test_case((0, 0, 2, 2), (1, 1, 2, 2), (0, 0, 3, 3))]
+    #[test_case((0, 0, 4, 4), (1, 1, 1, 1), (0, 0, 4, 4))]
+    #[test_case((-2, 3, 1, 1), (2, 0, 1, 1), (-2, 0, 5, 4))]
+    fn rect_union<R: Into<Rect<i32>>>(r: R, other: R, res: R) {
+        assert_eq!(r.into().union(other.into()), res.into());
+    }
+
     #[test_case((0, 0, 1, 1), (0, 0), true)]
     #[test_case((0, 0, 2, 2), (1, 1), true)]
     #[test_case((0, 0, 1, 1), (0, 1), true)]
@@ -0,0 +1,237 @@
+//! Project archives: an alternative container for projects, meant to be easy
+//! to inspect and fix by hand. An archive is a zip file with one PNG image per
+//! layer and a JSON manifest (`manifest.json`) with everything else. It holds
+//! the same data as the current project format version.
+
+use super::{v2, v3, FORMAT_VERSION};
+use crate::color::WHITE;
+use crate::{Bitmap, Error, Result, State};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// From bottom to top
+    layers: Vec<ManifestLayer>,
+    active_layer: u32,
+    main_color: [u8; 4],
+    // The fields with defaults were added in version 3
+    #[serde(default = "default_secondary_color")]
+    secondary_color: [u8; 4],
+    palette: Vec<[u8; 4]>,
+    #[serde(default)]
+    indexed: bool,
+    /// Number of columns and rows
+    spritesheet: (u8, u8),
+    #[serde(default)]
+    bucket: v3::ProjectBucket,
+    #[serde(default)]
+    gradient: v3::ProjectGradient,
+}
+
+fn default_secondary_color() -> [u8; 4] {
+    WHITE.into()
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ManifestLayer {
+    /// Path of the layer's PNG image inside the archive
+    file: String,
+    visible: bool,
+    opacity: u8,
+}
+
+/// Only the version of the manifest, which is read first so that newer
+/// manifests give a clear error
+#[derive(Deserialize)]
+struct ManifestVersion {
+    version: u32,
+}
+
+/// Encode a [`State`] as a project archive
+pub fn encode_archive<IMG>(state: &State<IMG>) -> Result<Vec<u8>>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let project = v3::Project::from_state(state)?;
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // PNG images are already compressed
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut layers = Vec::new();
+
+    for (i, layer) in project.layers.into_iter().enumerate() {
+        let file = format!("layers/{:03}.png", i);
+        zip.start_file(file.as_str(), stored)?;
+        zip.write_all(&layer.png)?;
+        layers.push(ManifestLayer {
+            file,
+            visible: layer.visible,
+            opacity: layer.opacity,
+        });
+    }
+
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        layers,
+        active_layer: project.active_layer,
+        main_color: project.main_color,
+        secondary_color: project.secondary_color,
+        palette: project.palette,
+        indexed: project.indexed,
+        spritesheet: project.spritesheet,
+        bucket: project.bucket,
+        gradient: project.gradient,
+    };
+    zip.start_file(MANIFEST, FileOptions::default())?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Decode a project archive into a new [`State`]
+pub fn decode_archive<IMG>(bytes: &[u8]) -> Result<State<IMG>>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+
+    let mut json = Vec::new();
+    zip.by_name(MANIFEST)?.read_to_end(&mut json)?;
+    match serde_json::from_slice::<ManifestVersion>(&json)?.version {
+        // Archives were added in version 2
+        2..=FORMAT_VERSION => (),
+        v if v > FORMAT_VERSION => return Err(Error::NewerProjectVersion(v)),
+        v => return Err(Error::UnknownProjectVersion(v.to_string())),
+    }
+    let manifest: Manifest = serde_json::from_slice(&json)?;
+
+    let mut layers = Vec::new();
+    for layer in manifest.layers {
+        let mut png = Vec::new();
+        zip.by_name(&layer.file)?.read_to_end(&mut png)?;
+        layers.push(v2::ProjectLayer {
+            png,
+            visible: layer.visible,
+            opacity: layer.opacity,
+        });
+    }
+
+    let project = v3::Project {
+        layers,
+        active_layer: manifest.active_layer,
+        main_color: manifest.main_color,
+        secondary_color: manifest.secondary_color,
+        palette: manifest.palette,
+        indexed: manifest.indexed,
+        spritesheet: manifest.spritesheet,
+        bucket: manifest.bucket,
+        gradient: manifest.gradient,
+    };
+
+    project.into_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::color::BLACK;
+    use crate::{Color, ColorMode, Event, Point};
+
+    #[test]
+    fn archive_contents() {
+        let mut state = State::<TestImage>::new((4, 2).into(), None, None);
+        state.execute(Event::NewLayerAbove).unwrap();
+        state.execute(Event::ChangeLayerOpacity(1, 100)).unwrap();
+
+        let bytes = encode_archive(&state).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        let mut names: Vec<_> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["layers/000.png", "layers/001.png", MANIFEST]);
+
+        let manifest: Manifest = serde_json::from_reader(zip.by_name(MANIFEST).unwrap()).unwrap();
+        assert_eq!(manifest.version, FORMAT_VERSION);
+        assert_eq!(manifest.layers[1].file, "layers/001.png");
+        assert_eq!(manifest.layers[1].opacity, 100);
+        assert_eq!(manifest.active_layer, 0);
+        assert_eq!(manifest.main_color, [0, 0, 0, 255]);
+        assert_eq!(manifest.spritesheet, (1, 1));
+    }
+
+    #[test]
+    fn archive_round_trip() {
+        let mut state = State::<TestImage>::new((4, 4).into(), None, None);
+        state.execute(Event::BrushStart).unwrap();
+        state.execute(Event::BrushStroke(Point::new(2, 1))).unwrap();
+        state.execute(Event::BrushEnd).unwrap();
+        state.execute(Event::NewLayerAbove).unwrap();
+        state
+            .execute(Event::ChangeLayerVisibility(1, false))
+            .unwrap();
+
+        let bytes = encode_archive(&state).unwrap();
+        let loaded = decode_archive::<TestImage>(&bytes).unwrap();
+
+        assert_eq!(loaded.layers().count(), 2);
+        assert_eq!(loaded.layers().canvas_at(0).pixel(Point::new(2, 1)), BLACK);
+        assert!(!loaded.layers().get(1).visible());
+        assert_eq!(loaded.palette(), state.palette());
+    }
+
+    #[test]
+    fn version_2_archive() {
+        let state = State::<TestImage>::new((4, 2).into(), None, None);
+        let bytes = encode_archive(&state).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(bytes.as_slice())).unwrap();
+        let png = {
+            let mut png = Vec::new();
+            zip.by_name("layers/000.png")
+                .unwrap()
+                .read_to_end(&mut png)
+                .unwrap();
+            png
+        };
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("layers/000.png", FileOptions::default())
+            .unwrap();
+        zip.write_all(&png).unwrap();
+        zip.start_file(MANIFEST, FileOptions::default()).unwrap();
+        let manifest = r#"{
+            "version": 2,
+            "layers": [{"file": "layers/000.png", "visible": true, "opacity": 255}],
+            "active_layer": 0,
+            "main_color": [1, 2, 3, 255],
+            "palette": [[0, 0, 0, 255]],
+            "spritesheet": [1, 1]
+        }"#;
+        zip.write_all(manifest.as_bytes()).unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let loaded = decode_archive::<TestImage>(&bytes).unwrap();
+        assert_eq!(loaded.main_color(), Color::new(1, 2, 3, 255));
+        assert_eq!(loaded.secondary_color(), WHITE);
+        assert_eq!(loaded.color_mode(), ColorMode::Rgba);
+        assert_eq!(loaded.gradient_options(), Default::default());
+    }
+
+    #[test]
+    fn newer_archive() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(MANIFEST, FileOptions::default()).unwrap();
+        zip.write_all(br#"{"version": 99, "something": "new"}"#)
+            .unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        assert!(matches!(
+            decode_archive::<TestImage>(&bytes),
+            Err(Error::NewerProjectVersion(99))
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+//! Reading and writing of project files (`.tarsila`). The [`save`] and
+//! [`load`] functions can be passed to [`State::new`] as the [`LoadProject`]
+//! and [`SaveProject`] hooks.
+//!
+//! A project file starts with a header containing the version of the editor
+//! that saved it and the version of the file format, followed by a magic
+//! number. The rest of the file depends on the format version. Files saved in
+//! older formats are migrated to the current one when loaded.
+//!
+//! Projects can also be stored as archives (see [`encode_archive`]), which are
+//! easier to inspect, but take more space.
+//!
+//! [`State::new`]: crate::State::new
+//! [`LoadProject`]: crate::LoadProject
+//! [`SaveProject`]: crate::SaveProject
+
+use crate::{Bitmap, Error, Result, State};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+mod archive;
+mod v1;
+mod v2;
+mod v3;
+
+pub use archive::{decode_archive, encode_archive};
+
+/// The version of the project file format written by this version of lapix
+pub const FORMAT_VERSION: u32 = 3;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MAGIC: [u8; 5] = [0xfa, 0x1a, 0xfe, 0x1b, 0xee];
+
+/// Write the bytes of a project to a file
+pub fn save(path: PathBuf, bytes: Vec<u8>) -> Result<()> {
+    std::fs::write(&path, bytes).map_err(|e| Error::SaveProjectFailed(path, e))
+}
+
+/// Read the bytes of a project from a file
+pub fn load(path: PathBuf) -> Result<Vec<u8>> {
+    std::fs::read(&path).map_err(|e| Error::LoadProjectFailed(path, e))
+}
+
+/// Encode a [`State`] as the contents of a project file, in the current
+/// format version
+pub fn encode<IMG>(state: &State<IMG>) -> Result<Vec<u8>>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let project = v3::Project::from_state(state)?;
+    let mut bytes = header();
+    bytes.append(&mut bincode::serialize(&project)?);
+
+    Ok(bytes)
+}
+
+/// Decode the contents of a project file into a new [`State`], migrating it
+/// from older format versions if needed
+pub fn decode<IMG>(bytes: &[u8]) -> Result<State<IMG>>
+where
+    IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+{
+    let (version, payload) = split_header(bytes)?;
+
+    let project = match version {
+        1 => bincode::deserialize::<v1::Project<IMG>>(payload)?
+            .migrate()?
+            .migrate(),
+        2 => bincode::deserialize::<v2::Project>(payload)?.migrate(),
+        _ => bincode::deserialize::<v3::Project>(payload)?,
+    };
+
+    project.into_state()
+}
+
+fn header() -> Vec<u8> {
+    let mut bytes = format!("tarsila {VERSION} format {FORMAT_VERSION} ").into_bytes();
+    bytes.extend_from_slice(&MAGIC);
+
+    bytes
+}
+
+/// Get the format version from the header of a project file, and the bytes
+/// that follow the header. Files saved by lapix 0.1.x don't state the format
+/// version, and are version 1.
+fn split_header(bytes: &[u8]) -> Result<(u32, &[u8])> {
+    let pos = bytes
+        .windows(MAGIC.len())
+        .position(|win| win == MAGIC)
+        .ok_or(Error::InvalidProjectHeader)?;
+    let header = std::str::from_utf8(&bytes[..pos]).map_err(|_| Error::InvalidProjectHeader)?;
+
+    let version = match header.split_whitespace().collect::<Vec<_>>()[..] {
+        ["tarsila", _] => 1,
+        ["tarsila", _, "format", version] => match version.parse() {
+            Ok(0) | Err(_) => return Err(Error::UnknownProjectVersion(version.to_owned())),
+            Ok(v) if v > FORMAT_VERSION => return Err(Error::NewerProjectVersion(v)),
+            Ok(v) => v,
+        },
+        _ => return Err(Error::InvalidProjectHeader),
+    };
+
+    Ok((version, &bytes[pos + MAGIC.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn with_magic(header: &str) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(42);
+
+        bytes
+    }
+
+    #[test_case("tarsila 0.1.2 ", 1)]
+    #[test_case("tarsila 0.1.0 format 1 ", 1)]
+    #[test_case("tarsila 0.2.0 format 2 ", 2)]
+    #[test_case("tarsila 0.2.0 format 3 ", 3)]
+    fn header_version(header: &str, version: u32) {
+        let bytes = with_magic(header);
+        assert_eq!(split_header(&bytes).unwrap(), (version, [42].as_slice()));
+    }
+
+    #[test]
+    fn current_header() {
+        let mut bytes = header();
+        bytes.push(42);
+        assert_eq!(
+            split_header(&bytes).unwrap(),
+            (FORMAT_VERSION, [42].as_slice())
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let bytes = with_magic("tarsila 9.0.0 format 99 ");
+        assert!(matches!(
+            split_header(&bytes),
+            Err(Error::NewerProjectVersion(99))
+        ));
+
+        let bytes = with_magic("tarsila 0.1.2 format x ");
+        assert!(matches!(
+            split_header(&bytes),
+            Err(Error::UnknownProjectVersion(v)) if v == "x"
+        ));
+
+        let bytes = with_magic("something else ");
+        assert!(matches!(
+            split_header(&bytes),
+            Err(Error::InvalidProjectHeader)
+        ));
+
+        assert!(matches!(
+            split_header(b"tarsila 0.1.2 "),
+            Err(Error::InvalidProjectHeader)
+        ));
+    }
+}
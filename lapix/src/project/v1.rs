@@ -0,0 +1,88 @@
+//! Format version 1, written by lapix 0.1.x: a bincode dump of the editor's
+//! `State`. The types below mirror the serialized layout of the in-memory
+//! types of that version (a struct with a single field is serialized as that
+//! field, and a struct of numbers as a tuple), so they must not change.
+
+use super::v2;
+use crate::{Bitmap, Result};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Project<IMG> {
+    layers: Layers<IMG>,
+    // Session data that is not part of later versions, but still needs to be
+    // read to get to the fields that follow it
+    _tool: Tool,
+    main_color: [u8; 4],
+    spritesheet: (u8, u8),
+    palette: Vec<[u8; 4]>,
+    _selection: Option<Selection>,
+    _free_image: Option<FreeImage<IMG>>,
+    _clipboard: Option<IMG>,
+}
+
+#[derive(Deserialize)]
+struct Layers<IMG> {
+    inner: Vec<Layer<IMG>>,
+    active: usize,
+}
+
+#[derive(Deserialize)]
+struct Layer<IMG> {
+    /// In 0.1.x this is a `Canvas`, which only holds an image
+    canvas: IMG,
+    visible: bool,
+    opacity: u8,
+}
+
+#[derive(Deserialize)]
+enum Tool {
+    Brush,
+    Eraser,
+    Eyedropper,
+    Bucket,
+    Line,
+    Selection,
+    Move,
+    Rectangle,
+    Ellipse,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+enum Selection {
+    Canvas((i32, i32, i32, i32)),
+    FreeImage,
+}
+
+#[derive(Deserialize)]
+struct FreeImage<IMG> {
+    _rect: (i32, i32, i32, i32),
+    _pivot: Option<(i32, i32)>,
+    _texture: IMG,
+}
+
+impl<IMG: Bitmap> Project<IMG> {
+    /// Convert this project to format version 2. The tool, selection and
+    /// clipboard are dropped. Any free image had already been anchored to the
+    /// canvas when the project was saved.
+    pub fn migrate(self) -> Result<v2::Project> {
+        let mut layers = Vec::new();
+
+        for layer in self.layers.inner {
+            layers.push(v2::ProjectLayer {
+                png: layer.canvas.png_bytes()?,
+                visible: layer.visible,
+                opacity: layer.opacity,
+            });
+        }
+
+        Ok(v2::Project {
+            layers,
+            active_layer: self.layers.active as u32,
+            main_color: self.main_color,
+            palette: self.palette,
+            spritesheet: self.spritesheet,
+        })
+    }
+}
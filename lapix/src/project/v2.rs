@@ -0,0 +1,44 @@
+//! Format version 2: the layers are stored as PNG images, and everything else
+//! as plain values, so that the file doesn't depend on the layout of the
+//! editor's in-memory types. Once released, these types must not change: a
+//! new format version (with a migration from this one) is needed instead.
+
+use super::v3;
+use crate::color::WHITE;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    /// From bottom to top
+    pub layers: Vec<ProjectLayer>,
+    pub active_layer: u32,
+    pub main_color: [u8; 4],
+    pub palette: Vec<[u8; 4]>,
+    /// Number of columns and rows
+    pub spritesheet: (u8, u8),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectLayer {
+    pub png: Vec<u8>,
+    pub visible: bool,
+    pub opacity: u8,
+}
+
+impl Project {
+    /// Convert this project to format version 3, with the default settings
+    /// for everything that version 2 doesn't store
+    pub fn migrate(self) -> v3::Project {
+        v3::Project {
+            layers: self.layers,
+            active_layer: self.active_layer,
+            main_color: self.main_color,
+            secondary_color: WHITE.into(),
+            palette: self.palette,
+            indexed: false,
+            spritesheet: self.spritesheet,
+            bucket: Default::default(),
+            gradient: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Format version 3: the same as version 2, plus the color mode, the secondary
+//! color and the settings of the tools. As in version 2, everything but the
+//! layers is stored as plain values, and these types must not change once
+//! released.
+
+use super::v2::ProjectLayer;
+use crate::{
+    Bitmap, BucketOptions, Color, ColorMode, Dither, Error, GradientOptions, GradientShape, Layer,
+    Layers, Palette, Result, Size, State,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    /// From bottom to top
+    pub layers: Vec<ProjectLayer>,
+    pub active_layer: u32,
+    pub main_color: [u8; 4],
+    pub secondary_color: [u8; 4],
+    pub palette: Vec<[u8; 4]>,
+    /// Whether the colors of the image are indexed by the palette
+    pub indexed: bool,
+    /// Number of columns and rows
+    pub spritesheet: (u8, u8),
+    pub bucket: ProjectBucket,
+    pub gradient: ProjectGradient,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectBucket {
+    pub tolerance: u8,
+    pub replace_all: bool,
+    pub diagonal: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectGradient {
+    pub radial: bool,
+    /// Width of the dithering matrix (2, 4 or 8)
+    pub dither: u8,
+    pub along_palette: bool,
+}
+
+impl Default for ProjectGradient {
+    fn default() -> Self {
+        GradientOptions::default().into()
+    }
+}
+
+impl From<BucketOptions> for ProjectBucket {
+    fn from(options: BucketOptions) -> Self {
+        Self {
+            tolerance: options.tolerance,
+            replace_all: options.replace_all,
+            diagonal: options.diagonal,
+        }
+    }
+}
+
+impl From<GradientOptions> for ProjectGradient {
+    fn from(options: GradientOptions) -> Self {
+        Self {
+            radial: options.shape == GradientShape::Radial,
+            dither: options.dither.size() as u8,
+            along_palette: options.along_palette,
+        }
+    }
+}
+
+impl Project {
+    pub fn from_state<IMG>(state: &State<IMG>) -> Result<Self>
+    where
+        IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+    {
+        let layers = state.layers();
+        let mut project_layers = Vec::new();
+
+        for i in 0..layers.count() {
+            let layer = layers.get(i);
+            project_layers.push(ProjectLayer {
+                png: layer.canvas().inner().png_bytes()?,
+                visible: layer.visible(),
+                opacity: layer.opacity(),
+            });
+        }
+
+        Ok(Self {
+            layers: project_layers,
+            active_layer: layers.active_index() as u32,
+            main_color: state.main_color().into(),
+            secondary_color: state.secondary_color().into(),
+            palette: state.palette().iter().map(|&c| c.into()).collect(),
+            indexed: state.color_mode() == ColorMode::Indexed,
+            spritesheet: (state.spritesheet().x, state.spritesheet().y),
+            bucket: state.bucket_options().into(),
+            gradient: state.gradient_options().into(),
+        })
+    }
+
+    pub fn into_state<IMG>(self) -> Result<State<IMG>>
+    where
+        IMG: Bitmap + Serialize + for<'de> Deserialize<'de>,
+    {
+        let mut layers = Vec::new();
+
+        for project_layer in self.layers {
+            let img = IMG::try_from_file_bytes(project_layer.png)?;
+            let mut layer = Layer::new(Size::ZERO);
+            layer.canvas_mut().set_img(img);
+            layer.set_visibility(project_layer.visible);
+            layer.set_opacity(project_layer.opacity);
+            layers.push(layer);
+        }
+
+        let size = match layers.first() {
+            Some(layer) => layer.canvas().size(),
+            None => return Err(Error::CorruptProject("there are no layers")),
+        };
+        if layers.iter().any(|layer| layer.canvas().size() != size) {
+            return Err(Error::CorruptProject("layers have different sizes"));
+        }
+        let active = self.active_layer as usize;
+        if active >= layers.len() {
+            return Err(Error::CorruptProject("the active layer does not exist"));
+        }
+
+        let dither = match self.gradient.dither {
+            2 => Dither::Bayer2,
+            4 => Dither::Bayer4,
+            8 => Dither::Bayer8,
+            _ => return Err(Error::CorruptProject("unknown dithering size")),
+        };
+        if self.indexed && self.palette.is_empty() {
+            return Err(Error::CorruptProject("indexed colors without a palette"));
+        }
+        let palette = Palette::new(self.palette.into_iter().map(Color::from).collect());
+
+        let mut state = State::from_project(
+            Layers::from_vec(layers, active),
+            self.main_color.into(),
+            palette,
+            self.spritesheet.into(),
+        );
+        state.restore_settings(
+            self.secondary_color.into(),
+            if self.indexed {
+                ColorMode::Indexed
+            } else {
+                ColorMode::Rgba
+            },
+            BucketOptions {
+                tolerance: self.bucket.tolerance.min(100),
+                replace_all: self.bucket.replace_all,
+                diagonal: self.bucket.diagonal,
+            },
+            GradientOptions {
+                shape: if self.gradient.radial {
+                    GradientShape::Radial
+                } else {
+                    GradientShape::Linear
+                },
+                dither,
+                along_palette: self.gradient.along_palette,
+            },
+        );
+
+        Ok(state)
+    }
+}
@@ -1,10 +1,16 @@
-use crate::color::{BLACK, TRANSPARENT};
+use crate::color::{BLACK, TRANSPARENT, WHITE};
+use crate::gradient::Gradient;
+use crate::tile::TileDiff;
 use crate::util::{LoadProject, SaveProject};
 use crate::{
-    util, Action, AtomicAction, Bitmap, Canvas, CanvasEffect, Color, Error, Event, FreeImage,
-    Layers, Palette, Point, Position, Rect, Result, Size, Tool,
+    animation, aseprite, atlas, export, indexed, project, util, Action, AtomicAction, Bitmap,
+    BucketOptions, Canvas, CanvasEffect, Color, ColorMode, DirtyRect, Error, Event, ExportMode,
+    FreeImage, GradientOptions, Journal, Layers, Palette, Point, Position, Rect, Result, Size,
+    Span, Tool,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 
 /// Represents a selection
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -17,6 +23,30 @@ pub enum Selection {
     FreeImage,
 }
 
+/// Limits on how much undo history is kept. When any of the limits is
+/// exceeded, the oldest undoable actions are dropped. `None` means no limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndoLimit {
+    /// Maximum number of actions that can be undone
+    pub max_steps: Option<usize>,
+    /// Maximum (estimated) memory, in bytes, taken by the undo and redo
+    /// history
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for UndoLimit {
+    fn default() -> Self {
+        Self {
+            max_steps: Some(1000),
+            max_bytes: Some(256 * 1024 * 1024),
+        }
+    }
+}
+
+fn default_secondary_color() -> Color {
+    WHITE
+}
+
 /// The state of the image editor's core. Most importantly, this contains all
 /// the layers and images that are being drawn. This state can be modified
 /// externally mainly by sending [`Event`]s via the [`execute`] method.
@@ -27,21 +57,39 @@ pub struct State<IMG> {
     layers: Layers<IMG>,
     #[serde(skip)]
     events: Vec<Event>,
+    #[serde(skip)]
+    journal: Journal,
     tool: Tool,
+    #[serde(default)]
+    bucket: BucketOptions,
+    #[serde(default)]
+    gradient: GradientOptions,
     main_color: Color,
+    #[serde(default = "default_secondary_color")]
+    secondary_color: Color,
     spritesheet: Size<u8>,
     palette: Palette,
+    #[serde(default)]
+    color_mode: ColorMode,
     selection: Option<Selection>,
     free_image: Option<FreeImage<IMG>>,
     clipboard: Option<IMG>,
-    #[serde(skip, default = "Vec::new")]
-    reversals: Vec<Action<IMG>>,
+    #[serde(skip, default = "VecDeque::new")]
+    reversals: VecDeque<Action<IMG>>,
+    #[serde(skip, default = "VecDeque::new")]
+    redos: VecDeque<Action<IMG>>,
+    #[serde(skip, default = "UndoLimit::default")]
+    undo_limit: UndoLimit,
     #[serde(skip, default = "Option::default")]
     cur_reversal: Option<Action<IMG>>,
     #[serde(skip, default = "Option::default")]
+    dirty: Option<DirtyRect>,
+    #[serde(skip, default = "Option::default")]
     load_project_fn: Option<LoadProject>,
     #[serde(skip, default = "Option::default")]
     save_project_fn: Option<SaveProject>,
+    #[serde(skip)]
+    warnings: Vec<String>,
 }
 
 impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
@@ -54,20 +102,60 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         Self {
             layers: Layers::new(size),
             events: Vec::new(),
+            journal: Journal::new(size),
             tool: Tool::Brush,
+            bucket: BucketOptions::default(),
+            gradient: GradientOptions::default(),
             main_color: BLACK,
+            secondary_color: WHITE,
             spritesheet: Size::new(1, 1),
             palette: Palette::default(),
+            color_mode: ColorMode::Rgba,
             selection: None,
             free_image: None,
             clipboard: None,
-            reversals: Vec::new(),
+            reversals: VecDeque::new(),
+            redos: VecDeque::new(),
+            undo_limit: UndoLimit::default(),
             cur_reversal: None,
+            dirty: None,
             load_project_fn,
             save_project_fn,
+            warnings: Vec::new(),
         }
     }
 
+    /// Create a state with the contents of a project file
+    pub(crate) fn from_project(
+        layers: Layers<IMG>,
+        main_color: Color,
+        palette: Palette,
+        spritesheet: Size<u8>,
+    ) -> Self {
+        let mut state = Self::new(Size::ZERO, None, None);
+        state.journal = Journal::new(layers.active_canvas().size());
+        state.layers = layers;
+        state.main_color = main_color;
+        state.palette = palette;
+        state.spritesheet = spritesheet;
+
+        state
+    }
+
+    /// Restore the settings saved in a project along with its image
+    pub(crate) fn restore_settings(
+        &mut self,
+        secondary_color: Color,
+        color_mode: ColorMode,
+        bucket: BucketOptions,
+        gradient: GradientOptions,
+    ) {
+        self.secondary_color = secondary_color;
+        self.color_mode = color_mode;
+        self.bucket = bucket;
+        self.gradient = gradient;
+    }
+
     fn start_action(&mut self) {
         self.cur_reversal = Some(Action::default());
     }
@@ -86,30 +174,122 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
 
     fn end_action(&mut self) {
         if let Some(action) = self.cur_reversal.take() {
-            self.reversals.push(action);
+            self.add_to_history(action);
         }
     }
 
     fn single_action(&mut self, action: Action<IMG>) {
         self.end_action();
-        self.reversals.push(action);
+        self.add_to_history(action);
+    }
+
+    /// Record a new action that can be undone. Once something new is done,
+    /// the actions that were undone before can't be redone anymore.
+    fn add_to_history(&mut self, action: Action<IMG>) {
+        self.redos.clear();
+        self.push_reversal(action);
+    }
+
+    fn push_reversal(&mut self, mut action: Action<IMG>) {
+        action.compact();
+        self.reversals.push_back(action);
+        self.enforce_undo_limit();
+    }
+
+    /// Set the limits on how much undo history is kept. If the current
+    /// history exceeds them, the oldest actions are dropped right away. This
+    /// is the same as executing [`Event::SetUndoLimit`].
+    pub fn set_undo_limit(&mut self, limit: UndoLimit) {
+        self.apply_undo_limit(limit);
+        self.journal.record(Event::SetUndoLimit(limit));
+    }
+
+    fn apply_undo_limit(&mut self, limit: UndoLimit) {
+        self.undo_limit = limit;
+        self.enforce_undo_limit();
+    }
+
+    /// Get the limits on how much undo history is kept
+    pub fn undo_limit(&self) -> UndoLimit {
+        self.undo_limit
+    }
+
+    /// Get an estimate of the memory taken by the undo and redo history, in
+    /// bytes
+    pub fn undo_memory_size(&self) -> usize {
+        self.reversals
+            .iter()
+            .chain(self.redos.iter())
+            .map(Action::memory_size)
+            .sum()
+    }
+
+    fn enforce_undo_limit(&mut self) {
+        if let Some(max_steps) = self.undo_limit.max_steps {
+            while self.reversals.len() > max_steps {
+                self.reversals.pop_front();
+            }
+        }
+
+        if let Some(max_bytes) = self.undo_limit.max_bytes {
+            let mut size = self.undo_memory_size();
+
+            while size > max_bytes {
+                // Oldest undo steps go first, then the redo steps that are
+                // furthest away from the current state
+                let dropped = match self.reversals.pop_front() {
+                    Some(action) => action,
+                    None => match self.redos.pop_front() {
+                        Some(action) => action,
+                        None => break,
+                    },
+                };
+                size -= dropped.memory_size();
+            }
+        }
     }
 
     fn add_to_pixels_action(&mut self, actions: Vec<(Point<i32>, Color)>) -> Result<()> {
+        self.mark_dirty(&actions);
         let actions = AtomicAction::set_pixel_vec(self.layers.active_index(), actions);
 
         self.add_to_action(actions)
     }
 
     fn single_pixels_action(&mut self, actions: Vec<(Point<i32>, Color)>) {
+        self.mark_dirty(&actions);
         let actions = AtomicAction::set_pixel_vec(self.layers.active_index(), actions);
         self.single_action(actions.into());
     }
 
+    fn single_spans_action(&mut self, spans: Vec<Span>) {
+        let index = self.layers.active_index();
+        let dirty = Span::bounds(&spans).map(|rect| DirtyRect::new(index, rect));
+        self.merge_dirty(dirty);
+        self.single_action(vec![AtomicAction::SetSpans(index, spans)].into());
+    }
+
+    /// Keep track of the pixels changed in the active layer by the event being
+    /// executed
+    fn mark_dirty(&mut self, pixels: &[(Point<i32>, Color)]) {
+        let index = self.layers.active_index();
+        let dirty = DirtyRect::from_points(index, pixels.iter().map(|(p, _)| *p));
+        self.merge_dirty(dirty);
+    }
+
+    fn merge_dirty(&mut self, dirty: Option<DirtyRect>) {
+        self.dirty = match (self.dirty.take(), dirty) {
+            (Some(a), Some(b)) => Some(a.merge(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
     /// Execute an [`Event`]. This is the main way of changing the editor's
     /// state, and probably the most central method of this library. A
     /// [`CanvasEffect`] is returned to communicate to the caller what kind of
-    /// visual updates must be made.
+    /// visual updates must be made. When only some pixels were changed, the
+    /// effect is [`CanvasEffect::Pixels`] with the layers and area affected, so
+    /// that the caller can update only that region.
     pub fn execute(&mut self, event: Event) -> Result<CanvasEffect> {
         if let Some(prev_event) = self.events.last() {
             if (prev_event == &event && !event.repeatable())
@@ -119,8 +299,7 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
             }
         }
 
-        dbg!(&event);
-        let t0 = std::time::SystemTime::now();
+        self.dirty = None;
 
         if event.triggers_anchoring() {
             self.anchor()?;
@@ -131,23 +310,18 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         match event.clone() {
             Event::ClearCanvas => {
                 let img = self.canvas_mut().clear();
-                let reversal = AtomicAction::SetLayerCanvas(self.layers.active_index(), img);
-                self.start_action();
-                self.add_to_action(vec![reversal])?;
-                self.end_action();
+                let diff = TileDiff::from_img_outside(&img, Size::ZERO);
+                let reversal = AtomicAction::SetTiles(self.layers.active_index(), diff);
+                self.single_action(vec![reversal].into());
             }
             Event::ResizeCanvas(size) => {
-                self.start_action();
                 let imgs = self.resize_canvas(size);
-                self.add_to_action(
-                    imgs.into_iter()
-                        .enumerate()
-                        .map(|(i, img)| AtomicAction::SetLayerCanvas(i, img))
-                        .collect(),
-                )?;
-                self.end_action();
+                self.single_action(AtomicAction::resize_vec(imgs, size).into());
             }
-            Event::LineStart(_) | Event::RectStart(_) | Event::EllipseStart(_) => (),
+            Event::LineStart(_)
+            | Event::RectStart(_)
+            | Event::EllipseStart(_)
+            | Event::GradientStart(_) => (),
             Event::BrushStart | Event::EraseStart => self.start_action(),
             Event::BrushEnd | Event::EraseEnd => self.end_action(),
             Event::LineEnd(p) => {
@@ -183,6 +357,16 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
                 self.single_pixels_action(reversals);
                 self.free_image = None;
             }
+            Event::GradientEnd(p) => {
+                let last_event = self.events.last();
+                let p0: Point<i32> = match last_event {
+                    Some(Event::GradientStart(p0)) => *p0,
+                    _ => return Err(Error::DrawingNotStarted),
+                };
+                // Remove the preview before painting
+                self.free_image = None;
+                self.gradient(p0, p)?;
+            }
             Event::BrushStroke(p) => {
                 let last_event = self.events.last();
 
@@ -218,39 +402,91 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
                 self.add_to_pixels_action(reversals)?;
             }
             Event::SetTool(tool) => self.tool = tool,
-            Event::SetMainColor(color) => self.main_color = color,
-            Event::Save(path) => self.save_image(path.to_string_lossy().as_ref())?,
-            Event::OpenFile(path) => self.import_image(path.to_string_lossy().as_ref())?,
-            Event::SaveProject(path) => {
-                if let Some(f) = &self.save_project_fn {
-                    let bytes = bincode::serialize(&self)?;
-                    (f.0)(path, bytes);
-                } else {
-                    eprintln!("Bug: Missing save project function");
+            Event::SetBucketOptions(options) => self.bucket = options,
+            Event::SetGradientOptions(options) => self.gradient = options,
+            Event::SetUndoLimit(limit) => self.apply_undo_limit(limit),
+            Event::SetSecondaryColor(color) => self.secondary_color = color,
+            Event::SetMainColor(color) => {
+                let color = match self.color_mode {
+                    ColorMode::Rgba => color,
+                    ColorMode::Indexed => indexed::palette_color(self.palette(), color),
+                };
+                if color != self.main_color {
+                    let reversal = AtomicAction::SetMainColor(self.main_color);
+                    self.main_color = color;
+                    self.single_action(vec![reversal].into());
                 }
             }
-            Event::LoadProject(path) => {
-                if let Some(f) = &self.load_project_fn {
-                    let bytes = (f.0)(path);
-                    let (save_fn, load_fn) =
-                        (self.save_project_fn.take(), self.load_project_fn.take());
-                    *self = bincode::deserialize(&bytes)?;
-                    self.save_project_fn = save_fn;
-                    self.load_project_fn = load_fn;
-                } else {
-                    eprintln!("Bug: Missing load project function");
+            Event::Save(path) => self.export_image(&path, 1, &ExportMode::VisibleLayers, false)?,
+            Event::Export {
+                path,
+                scale,
+                mode,
+                indexed,
+            } => self.export_image(&path, scale, &mode, indexed)?,
+            Event::ExportGif(path, options) => {
+                let img = self.layers.blended();
+                let gif = animation::encode_gif(&img, self.spritesheet, self.palette(), options)?;
+                std::fs::write(path, gif)?;
+            }
+            Event::ExportApng(path, options) => {
+                let img = self.layers.blended();
+                std::fs::write(
+                    path,
+                    animation::encode_apng(&img, self.spritesheet, options)?,
+                )?;
+            }
+            Event::ExportFrames(path) => {
+                let img = self.layers.blended();
+                for (path, png) in animation::encode_png_sequence(&img, self.spritesheet, &path)? {
+                    std::fs::write(path, png)?;
                 }
             }
+            Event::ExportAtlas {
+                path,
+                layout,
+                frame_delay,
+            } => {
+                self.export_image(&path, 1, &ExportMode::VisibleLayers, false)?;
+                let size = self.layers.canvas_at(0).size();
+                let json = atlas::to_json(&path, size, self.spritesheet, layout, frame_delay)?;
+                std::fs::write(path.with_extension("json"), json)?;
+            }
+            Event::OpenFile(path) => self.import_image(path.to_string_lossy().as_ref())?,
+            Event::ImportFrames { paths, columns } => self.import_frames(&paths, columns)?,
+            Event::SaveProject(path) => self.save_project(path, project::encode)?,
+            Event::LoadProject(path) => self.load_project(path, project::decode)?,
+            Event::SaveProjectArchive(path) => self.save_project(path, project::encode_archive)?,
+            Event::LoadProjectArchive(path) => self.load_project(path, project::decode_archive)?,
+            Event::ImportAseprite(path) => self.import_aseprite(path)?,
+            Event::ExportAseprite(path) => std::fs::write(path, aseprite::write(self)?)?,
             Event::LoadPalette(path) => {
-                self.palette = Palette::from_file(path.to_string_lossy().as_ref())?
+                let palette = Palette::from_file(path.to_string_lossy().as_ref())?;
+                self.set_palette(palette)?;
+            }
+            Event::SetBuiltinPalette(name) => {
+                let palette = Palette::builtin(&name).ok_or(Error::UnknownPalette(name))?;
+                self.set_palette(palette)?;
+            }
+            Event::EditPaletteColor(i, color) => self.edit_palette_color(i, color)?,
+            Event::SetColorMode(mode) => self.set_color_mode(mode)?,
+            Event::SavePalette(path) => self.palette.save(path.to_string_lossy().as_ref())?,
+            Event::AddToPalette(color) => {
+                let mut palette = self.palette.clone();
+                palette.add_color(color);
+                self.set_palette(palette)?;
+            }
+            Event::RemoveFromPalette(color) => {
+                let mut palette = self.palette.clone();
+                palette.remove_color(color);
+                self.set_palette(palette)?;
             }
-            Event::AddToPalette(color) => self.palette.add_color(color),
-            Event::RemoveFromPalette(color) => self.palette.remove_color(color),
             Event::Bucket(p) => {
                 if self.canvas().is_in_bounds(p) {
                     let color = self.main_color;
-                    let reversals = self.canvas_mut().bucket(p, color);
-                    self.single_pixels_action(reversals);
+                    let options = self.bucket;
+                    let reversals = self.canvas_mut().bucket(p, color, options);
+                    self.single_spans_action(reversals);
                 }
             }
             Event::ClearSelection => (),
@@ -357,9 +593,27 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
                 self.end_action();
             }
             Event::NewLayerBelow => todo!(),
-            Event::SwitchLayer(i) => self.layers.switch_to(i),
-            Event::ChangeLayerVisibility(i, visible) => self.layers.set_visibility(i, visible),
-            Event::ChangeLayerOpacity(i, alpha) => self.layers.set_opacity(i, alpha),
+            Event::SwitchLayer(i) => {
+                let old = self.layers.active_index();
+                if i != old {
+                    self.layers.switch_to(i);
+                    self.single_action(vec![AtomicAction::SetActiveLayer(old)].into());
+                }
+            }
+            Event::ChangeLayerVisibility(i, visible) => {
+                let old = self.layers.get(i).visible();
+                if visible != old {
+                    self.layers.set_visibility(i, visible);
+                    self.single_action(vec![AtomicAction::SetLayerVisibility(i, old)].into());
+                }
+            }
+            Event::ChangeLayerOpacity(i, alpha) => {
+                let old = self.layers.get(i).opacity();
+                if alpha != old {
+                    self.layers.set_opacity(i, alpha);
+                    self.single_action(vec![AtomicAction::SetLayerOpacity(i, old)].into());
+                }
+            }
             // TODO: this should not only remove it, as we need to be able to
             // undo this
             Event::DeleteLayer(i) => {
@@ -372,33 +626,38 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
                     .push(AtomicAction::CreateLayer(i, img));
                 self.end_action();
             }
-            Event::MoveLayerDown(i) => self.layers.swap(i, i - 1),
-            Event::MoveLayerUp(i) => self.layers.swap(i, i + 1),
+            Event::MoveLayerDown(i) => {
+                self.layers.swap(i, i - 1);
+                self.single_action(vec![AtomicAction::SwapLayers(i, i - 1)].into());
+            }
+            Event::MoveLayerUp(i) => {
+                self.layers.swap(i, i + 1);
+                self.single_action(vec![AtomicAction::SwapLayers(i, i + 1)].into());
+            }
             Event::SetSpritesheet(size) => self.set_spritesheet(size),
             Event::Undo => {
                 // TODO: we should add UNDO to the events list
-                #[allow(unused_must_use)]
-                {
-                    dbg!(t0.elapsed());
-                }
+                self.journal.record(event);
                 return Ok(self.undo());
             }
+            Event::Redo => {
+                self.journal.record(event);
+                return Ok(self.redo());
+            }
         }
 
         if event.clears_selection() {
             self.clear_selection()?;
         }
 
-        #[allow(unused_must_use)]
-        {
-            dbg!(t0.elapsed());
-        }
-
         if skip_event {
-            println!("Event skipped");
             Ok(CanvasEffect::None)
         } else {
-            let effect = event.canvas_effect();
+            let effect = match (event.canvas_effect(), self.dirty.take()) {
+                (CanvasEffect::Update, Some(dirty)) => CanvasEffect::Pixels(dirty),
+                (effect, _) => effect,
+            };
+            self.journal.record(event.clone());
             self.events.push(event);
 
             Ok(effect)
@@ -409,6 +668,17 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         self.layers.resize_all(size)
     }
 
+    /// Get the [`Journal`] with all the events accepted by this state so far
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Take the warnings produced by the events executed so far, e.g. about
+    /// parts of an imported file that are not supported
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
     /// Get a mutable reference to the active [`Layer`]'s [`Canvas`]
     ///
     /// [`Layer`]: crate::Layer
@@ -433,12 +703,27 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         self.tool
     }
 
+    /// Get the settings of the bucket tool
+    pub fn bucket_options(&self) -> BucketOptions {
+        self.bucket
+    }
+
+    /// Get the settings of the gradient tool
+    pub fn gradient_options(&self) -> GradientOptions {
+        self.gradient
+    }
+
     /// Get the main (selected) color. This is the color used by most tools
     /// when drawing
     pub fn main_color(&self) -> Color {
         self.main_color
     }
 
+    /// Get the secondary color, used by the gradient tool
+    pub fn secondary_color(&self) -> Color {
+        self.secondary_color
+    }
+
     /// Get the spritesheet dimensions (number of horizontal and vertical
     /// frames). For a static image (not an animation) it will be `(1, 1)`.
     pub fn spritesheet(&self) -> Size<u8> {
@@ -463,6 +748,128 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         self.palette.colors()
     }
 
+    /// Get the palette, including the names of its colors
+    pub(crate) fn full_palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub(crate) fn clipboard(&self) -> Option<&IMG> {
+        self.clipboard.as_ref()
+    }
+
+    /// Get the number of actions that can be undone and redone
+    pub(crate) fn history_len(&self) -> (usize, usize) {
+        (self.reversals.len(), self.redos.len())
+    }
+
+    /// Replace the palette, recording the previous one so it can be undone. In
+    /// indexed mode, repeated colors of the new palette are merged and the
+    /// layers are recolored with its closest colors.
+    fn set_palette(&mut self, mut palette: Palette) -> Result<()> {
+        if self.color_mode == ColorMode::Indexed {
+            palette.dedup();
+        }
+        if palette == self.palette {
+            return Ok(());
+        }
+        if self.color_mode == ColorMode::Indexed && palette.colors().is_empty() {
+            return Err(Error::EmptyPalette);
+        }
+
+        let old = std::mem::replace(&mut self.palette, palette);
+        let mut action = vec![AtomicAction::SetPalette(old)];
+        if self.color_mode == ColorMode::Indexed {
+            let colors = self.palette().to_vec();
+            action.append(&mut self.recolor(|c| indexed::palette_color(&colors, c)));
+        }
+        self.single_action(action.into());
+
+        Ok(())
+    }
+
+    /// Replace a color of the palette. In indexed mode, the pixels with that
+    /// color are recolored.
+    fn edit_palette_color(&mut self, index: usize, color: Color) -> Result<()> {
+        let old_color = *self
+            .palette()
+            .get(index)
+            .ok_or(Error::InvalidPaletteIndex(index))?;
+        if color == old_color {
+            return Ok(());
+        }
+        // Pixels with either color couldn't be told apart anymore
+        if self.color_mode == ColorMode::Indexed && self.palette().contains(&color) {
+            return Err(Error::DuplicatePaletteColor);
+        }
+
+        let mut palette = self.palette.clone();
+        palette.set_color(index, color);
+        let old = std::mem::replace(&mut self.palette, palette);
+        let mut action = vec![AtomicAction::SetPalette(old)];
+        if self.color_mode == ColorMode::Indexed {
+            action.append(&mut self.recolor(|c| if c == old_color { color } else { c }));
+        }
+        self.single_action(action.into());
+
+        Ok(())
+    }
+
+    /// Get the [`ColorMode`] of the layers
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    fn set_color_mode(&mut self, mode: ColorMode) -> Result<()> {
+        if mode == self.color_mode {
+            return Ok(());
+        }
+
+        let mut action = vec![AtomicAction::SetColorMode(self.color_mode)];
+        if mode == ColorMode::Indexed {
+            if self.palette().is_empty() {
+                return Err(Error::EmptyPalette);
+            }
+            let mut palette = self.palette.clone();
+            palette.dedup();
+            if palette != self.palette {
+                let old = std::mem::replace(&mut self.palette, palette);
+                action.push(AtomicAction::SetPalette(old));
+            }
+            let colors = self.palette().to_vec();
+            action.append(&mut self.recolor(|c| indexed::palette_color(&colors, c)));
+        }
+        self.color_mode = mode;
+        self.single_action(action.into());
+
+        Ok(())
+    }
+
+    /// Change the color of every pixel of every layer, and the main color.
+    /// Returns the actions needed to reverse it.
+    fn recolor(&mut self, map: impl Fn(Color) -> Color) -> Vec<AtomicAction<IMG>> {
+        let mut actions = Vec::new();
+
+        for i in 0..self.layers.count() {
+            let canvas = self.layers.canvas_at_mut(i);
+            let mut reversals = Vec::new();
+            for x in 0..canvas.width() {
+                for y in 0..canvas.height() {
+                    let p = Point::new(x, y);
+                    reversals.extend(canvas.set_pixel(p, map(canvas.pixel(p))));
+                }
+            }
+            actions.append(&mut AtomicAction::set_pixel_vec(i, reversals));
+        }
+
+        let main_color = map(self.main_color);
+        if main_color != self.main_color {
+            let old = std::mem::replace(&mut self.main_color, main_color);
+            actions.push(AtomicAction::SetMainColor(old));
+        }
+
+        actions
+    }
+
     /// Get the [`Selection`]
     pub fn selection(&self) -> Option<Selection> {
         self.selection
@@ -497,8 +904,18 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
     /// Anchor the [`FreeImage`] into the canvas.
     fn anchor(&mut self) -> Result<()> {
         if let Some(free_image) = self.free_image.take() {
-            println!("Anchoring");
             let reversals = self.canvas_mut().paste_obj(&free_image);
+            if self.color_mode == ColorMode::Indexed {
+                // Pasted colors (and their blending with the canvas) might not
+                // be in the palette. The reversals already restore the colors
+                // from before pasting.
+                let palette = self.palette().to_vec();
+                for &(p, _) in &reversals {
+                    let color = self.canvas().pixel(p);
+                    self.canvas_mut()
+                        .set_pixel(p, indexed::palette_color(&palette, color));
+                }
+            }
             self.single_pixels_action(reversals);
             self.set_selection(Some(Selection::Canvas(
                 free_image.rect.clip_to(self.canvas().rect()),
@@ -511,8 +928,38 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
     /// Undo the last undoable action. Returns the [`CanvasEffect`] to signal to
     /// the caller what needs to be updated visually
     fn undo(&mut self) -> CanvasEffect {
-        if let Some(action) = self.reversals.pop() {
-            return action.apply(&mut self.layers);
+        if let Some(action) = self.reversals.pop_back() {
+            let (effect, mut redo) = action.apply(
+                &mut self.layers,
+                &mut self.palette,
+                &mut self.main_color,
+                &mut self.spritesheet,
+                &mut self.color_mode,
+            );
+            redo.compact();
+            self.redos.push_back(redo);
+            self.enforce_undo_limit();
+
+            return effect;
+        }
+
+        CanvasEffect::None
+    }
+
+    /// Redo the last undone action. Returns the [`CanvasEffect`] to signal to
+    /// the caller what needs to be updated visually
+    fn redo(&mut self) -> CanvasEffect {
+        if let Some(action) = self.redos.pop_back() {
+            let (effect, reversal) = action.apply(
+                &mut self.layers,
+                &mut self.palette,
+                &mut self.main_color,
+                &mut self.spritesheet,
+                &mut self.color_mode,
+            );
+            self.push_reversal(reversal);
+
+            return effect;
         }
 
         CanvasEffect::None
@@ -529,6 +976,7 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
             Some(Event::LineStart(p)) => self.update_line_preview(*p, mouse_canvas),
             Some(Event::RectStart(p)) => self.update_rect_preview(*p, mouse_canvas),
             Some(Event::EllipseStart(p)) => self.update_ellipse_preview(*p, mouse_canvas),
+            Some(Event::GradientStart(p)) => self.update_line_preview(*p, mouse_canvas),
             _ => (),
         }
 
@@ -569,10 +1017,113 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         self.free_image = Some(FreeImage::ellipse_preview(p0, p, self.main_color()));
     }
 
-    fn save_image(&self, path: &str) -> Result<()> {
-        let blended = self.layers.blended();
+    /// Encode this state as a project and write it with the save project
+    /// function
+    fn save_project(&self, path: PathBuf, encode: fn(&Self) -> Result<Vec<u8>>) -> Result<()> {
+        let f = self
+            .save_project_fn
+            .as_ref()
+            .ok_or(Error::MissingSaveProjectFn)?;
+
+        (f.0)(path, encode(self)?)
+    }
+
+    /// Read a project with the load project function and replace this state
+    /// with it
+    fn load_project(&mut self, path: PathBuf, decode: fn(&[u8]) -> Result<Self>) -> Result<()> {
+        let f = self
+            .load_project_fn
+            .as_ref()
+            .ok_or(Error::MissingLoadProjectFn)?;
+        let bytes = (f.0)(path.clone())?;
+
+        // Only replace the current state once the project is known to be
+        // valid, so that a failed load doesn't lose any work
+        let loaded = decode(&bytes).map_err(|e| match e {
+            Error::CodecError(e) => Error::InvalidProjectFile(path, e),
+            e => e,
+        })?;
+        self.replace_with(loaded);
 
-        util::save_image(blended, path)
+        Ok(())
+    }
+
+    /// Replace this state with the contents of an Aseprite file. The main
+    /// color, the journal and the project functions are kept.
+    fn import_aseprite(&mut self, path: PathBuf) -> Result<()> {
+        let bytes = std::fs::read(path)?;
+        let sprite = aseprite::read(&bytes)?;
+
+        let mut imported = Self::from_project(
+            sprite.layers,
+            self.main_color,
+            sprite.palette,
+            sprite.spritesheet,
+        );
+        imported.warnings = sprite.warnings;
+        self.replace_with(imported);
+
+        Ok(())
+    }
+
+    /// Replace this state with another one, keeping the journal and the
+    /// project functions
+    fn replace_with(&mut self, mut other: Self) {
+        other.undo_limit = self.undo_limit;
+        other.save_project_fn = self.save_project_fn.take();
+        other.load_project_fn = self.load_project_fn.take();
+        other.journal = std::mem::take(&mut self.journal);
+        *self = other;
+    }
+
+    fn export_image(&self, path: &Path, scale: u8, mode: &ExportMode, indexed: bool) -> Result<()> {
+        if scale == 0 {
+            return Err(Error::InvalidExportScale);
+        }
+
+        let all: Vec<_> = (0..self.layers.count()).collect();
+        let images = match mode {
+            ExportMode::AllLayers => vec![(path.to_owned(), self.layers.blended_layers(&all))],
+            ExportMode::VisibleLayers => vec![(path.to_owned(), self.layers.blended())],
+            ExportMode::EachLayer => all
+                .iter()
+                .map(|&i| {
+                    (
+                        export::layer_path(path, i),
+                        self.layers.blended_layers(&[i]),
+                    )
+                })
+                .collect(),
+            ExportMode::Layers(indices) => {
+                if let Some(&i) = indices.iter().find(|&&i| i >= self.layers.count()) {
+                    return Err(Error::LayerNotFound(i));
+                }
+                // Layers are always blended from bottom to top
+                let mut indices = indices.clone();
+                indices.sort_unstable();
+                indices.dedup();
+                vec![(path.to_owned(), self.layers.blended_layers(&indices))]
+            }
+        };
+
+        // Only PNG images can have a palette
+        let format = image::ImageFormat::from_path(path).unwrap_or(image::ImageFormat::Png);
+        if indexed && format != image::ImageFormat::Png {
+            return Err(Error::UnsupportedImageFormat);
+        }
+
+        for (path, img) in images {
+            let img = match scale {
+                1 => img,
+                _ => util::scale_image(&img, scale),
+            };
+            match indexed {
+                true => std::fs::write(path, indexed::encode_png(&img, self.palette())?)?,
+                false => util::save_image(img, path.to_string_lossy().as_ref())?,
+            }
+        }
+
+        Ok(())
     }
 
     fn import_image(&mut self, path: &str) -> Result<()> {
@@ -581,7 +1132,9 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
         if img.width() as i32 > self.canvas().width()
             || img.height() as i32 > self.canvas().height()
         {
-            self.resize_canvas((img.width() as i32, img.height() as i32).into());
+            let size = (img.width() as i32, img.height() as i32).into();
+            let imgs = self.resize_canvas(size);
+            self.single_action(AtomicAction::resize_vec(imgs, size).into());
         }
 
         let img: IMG = util::img_from_raw(img);
@@ -591,4 +1144,103 @@ impl<IMG: Bitmap + Serialize + for<'de> Deserialize<'de>> State<IMG> {
 
         Ok(())
     }
+
+    fn import_frames(&mut self, paths: &[PathBuf], columns: u8) -> Result<()> {
+        let frames = paths
+            .iter()
+            .map(|path| util::load_img_from_file(path.to_string_lossy().as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let first = frames.first().ok_or(Error::NoFrames)?;
+        let frame_size = Size::new(first.width() as i32, first.height() as i32);
+        for (path, frame) in paths.iter().zip(&frames) {
+            let size = Size::new(frame.width() as i32, frame.height() as i32);
+            if size != frame_size {
+                return Err(Error::FrameSizeMismatch(path.clone(), size, frame_size));
+            }
+        }
+
+        // A last row that is not full leaves its trailing cells empty
+        let rows = match columns {
+            0 => None,
+            _ => u8::try_from(frames.len().div_ceil(columns as usize)).ok(),
+        };
+        let rows = rows.ok_or(Error::InvalidFrameGrid(frames.len(), columns))?;
+        let grid = Size::new(columns, rows);
+
+        let size = Size::new(frame_size.x * columns as i32, frame_size.y * rows as i32);
+        // Resizes all layers, and the same action undoes it for all of them
+        let imgs = self.resize_canvas(size);
+        let mut action = Action::from(AtomicAction::resize_vec(imgs, size));
+
+        let active = self.layers.active_index();
+        let old = self.canvas_mut().clear();
+        action.push(AtomicAction::SetTiles(
+            active,
+            TileDiff::from_img_outside(&old, Size::ZERO),
+        ));
+
+        let mut reversals = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let origin = Point::new(
+                (i % columns as usize) as i32 * frame_size.x,
+                (i / columns as usize) as i32 * frame_size.y,
+            );
+            for (x, y, pixel) in frame.enumerate_pixels() {
+                let [r, g, b, a] = pixel.0;
+                let color = match self.color_mode {
+                    ColorMode::Rgba => Color::new(r, g, b, a),
+                    ColorMode::Indexed => {
+                        indexed::palette_color(self.palette(), Color::new(r, g, b, a))
+                    }
+                };
+                let p = origin + Point::new(x as i32, y as i32);
+                reversals.extend(self.canvas_mut().set_pixel(p, color));
+            }
+        }
+        action.append(AtomicAction::set_pixel_vec(active, reversals));
+
+        action.push(AtomicAction::SetSpritesheet(self.spritesheet));
+        self.spritesheet = grid;
+        self.single_action(action);
+
+        Ok(())
+    }
+
+    /// Fill the selection, or the area the bucket would fill from `start`, with
+    /// a gradient from `start` to `end`
+    fn gradient(&mut self, start: Point<i32>, end: Point<i32>) -> Result<()> {
+        let colors = match self.gradient.along_palette {
+            true if self.palette().is_empty() => return Err(Error::EmptyPalette),
+            true => self.palette().to_vec(),
+            false => vec![self.main_color, self.secondary_color],
+        };
+        let rows = match self.selection {
+            Some(Selection::Canvas(rect)) => {
+                let rect = rect.clip_to(self.canvas().rect());
+                (rect.y..rect.y + rect.h)
+                    .map(|y| (Point::new(rect.x, y), rect.w))
+                    .collect()
+            }
+            _ if self.canvas().is_in_bounds(start) => self.canvas().area(start, self.bucket),
+            _ => return Ok(()),
+        };
+
+        let gradient = Gradient::new(colors, start, end, self.gradient);
+        let palette = self.palette().to_vec();
+        let mut reversals = Vec::new();
+        for (row, len) in rows {
+            for x in row.x..row.x + len {
+                let p = Point::new(x, row.y);
+                let color = match self.color_mode {
+                    ColorMode::Rgba => gradient.color_at(p),
+                    ColorMode::Indexed => indexed::palette_color(&palette, gradient.color_at(p)),
+                };
+                reversals.extend(self.canvas_mut().set_pixel(p, color));
+            }
+        }
+        self.single_pixels_action(reversals);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,260 @@
+use crate::color::TRANSPARENT;
+use crate::{Bitmap, Canvas, Color, Point, Rect, Size};
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+/// Side (in pixels) of the square tiles in which a [`TileDiff`] is stored
+pub const TILE_SIDE: i32 = 16;
+const TILE_AREA: usize = (TILE_SIDE * TILE_SIDE) as usize;
+
+/// A sequence of pixels inside a [`Tile`], in row-major order
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Run {
+    /// Pixels that are not affected by the diff
+    Skip(u16),
+    /// Pixels that must be set to a color
+    Fill(u16, Color),
+}
+
+/// A run-length encoded square area of a layer
+#[derive(Debug, Clone, PartialEq)]
+struct Tile {
+    pos: Point<i32>,
+    runs: Vec<Run>,
+}
+
+/// A compact representation of a set of pixel changes in a layer, used to
+/// store undo and redo data. Pixels are grouped in tiles of [`TILE_SIDE`] by
+/// [`TILE_SIDE`] pixels, and each tile is run-length encoded, so that large
+/// areas with the same color take very little memory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileDiff(Vec<Tile>);
+
+impl TileDiff {
+    /// Create a diff from a set of points and the colors they must be set to.
+    /// If the same point shows up more than once, the first color is kept.
+    pub fn from_pixels(pixels: Vec<(Point<i32>, Color)>) -> Self {
+        let mut tiles: BTreeMap<Point<i32>, Vec<Option<Color>>> = BTreeMap::new();
+
+        for (p, color) in pixels {
+            let pos = Point::new(
+                p.x.div_euclid(TILE_SIDE) * TILE_SIDE,
+                p.y.div_euclid(TILE_SIDE) * TILE_SIDE,
+            );
+            let tile = tiles.entry(pos).or_insert_with(|| vec![None; TILE_AREA]);
+            let index = ((p.y - pos.y) * TILE_SIDE + p.x - pos.x) as usize;
+            tile[index].get_or_insert(color);
+        }
+
+        Self(
+            tiles
+                .into_iter()
+                .map(|(pos, pixels)| Tile {
+                    pos,
+                    runs: encode(&pixels),
+                })
+                .collect(),
+        )
+    }
+
+    /// Create a diff with the non-transparent pixels of an image that fall
+    /// outside of an area of a certain size starting at (0, 0). This is what
+    /// is needed to restore an image after it is cleared (size zero) or shrunk
+    /// to that size.
+    pub fn from_img_outside<IMG: Bitmap>(img: &IMG, size: Size<i32>) -> Self {
+        let mut pixels = Vec::new();
+
+        for i in 0..img.width() {
+            for j in 0..img.height() {
+                if i < size.x && j < size.y {
+                    continue;
+                }
+
+                let p = Point::new(i, j);
+                let color = img.pixel(p);
+                if color != TRANSPARENT {
+                    pixels.push((p, color));
+                }
+            }
+        }
+
+        Self::from_pixels(pixels)
+    }
+
+    /// Set the pixels of a canvas according to this diff. Returns the diff
+    /// needed to reverse the changes.
+    pub fn apply<IMG: Bitmap>(&self, canvas: &mut Canvas<IMG>) -> Self {
+        let mut reversals = Vec::new();
+
+        for tile in &self.0 {
+            let mut index = 0;
+
+            for run in &tile.runs {
+                match *run {
+                    Run::Skip(n) => index += n as i32,
+                    Run::Fill(n, color) => {
+                        for _ in 0..n {
+                            let p = tile.pos + Point::new(index % TILE_SIDE, index / TILE_SIDE);
+                            reversals.extend(canvas.set_pixel(p, color));
+                            index += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        Self::from_pixels(reversals)
+    }
+
+    /// Get the rectangle that bounds all the pixels changed by this diff,
+    /// clipped to a certain area. Returns `None` if the diff is empty
+    pub fn bounds(&self, area: Rect<i32>) -> Option<Rect<i32>> {
+        self.0
+            .iter()
+            .flat_map(|tile| {
+                let mut index = 0;
+                tile.runs.iter().filter_map(move |run| match *run {
+                    Run::Skip(n) => {
+                        index += n as i32;
+                        None
+                    }
+                    Run::Fill(n, _) => {
+                        let (first, last) = (index, index + n as i32 - 1);
+                        index += n as i32;
+
+                        // A run can span several rows of the tile
+                        let (x0, x1) = if first / TILE_SIDE == last / TILE_SIDE {
+                            (first % TILE_SIDE, last % TILE_SIDE)
+                        } else {
+                            (0, TILE_SIDE - 1)
+                        };
+                        let (y0, y1) = (first / TILE_SIDE, last / TILE_SIDE);
+
+                        Some(Rect::new(
+                            tile.pos.x + x0,
+                            tile.pos.y + y0,
+                            x1 - x0 + 1,
+                            y1 - y0 + 1,
+                        ))
+                    }
+                })
+            })
+            .reduce(Rect::union)
+            .map(|rect| rect.clip_to(area))
+    }
+
+    /// Whether this diff does not change any pixel
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get an estimate of the memory taken by this diff, in bytes
+    pub fn memory_size(&self) -> usize {
+        self.0
+            .iter()
+            .map(|tile| size_of::<Tile>() + tile.runs.len() * size_of::<Run>())
+            .sum()
+    }
+}
+
+fn encode(pixels: &[Option<Color>]) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+
+    while i < pixels.len() {
+        let mut n = 1;
+        while i + n < pixels.len() && pixels[i + n] == pixels[i] {
+            n += 1;
+        }
+
+        match pixels[i] {
+            Some(color) => runs.push(Run::Fill(n as u16, color)),
+            None if i + n < pixels.len() => runs.push(Run::Skip(n as u16)),
+            // Trailing pixels that are not affected don't need to be stored
+            None => (),
+        }
+
+        i += n;
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::color::BLACK;
+
+    #[test]
+    fn encode_runs() {
+        let red = Color::new(255, 0, 0, 255);
+        let pixels = [None, None, Some(red), Some(red), Some(BLACK), None];
+
+        assert_eq!(
+            encode(&pixels),
+            vec![Run::Skip(2), Run::Fill(2, red), Run::Fill(1, BLACK)]
+        );
+    }
+
+    #[test]
+    fn first_color_is_kept() {
+        let red = Color::new(255, 0, 0, 255);
+        let diff = TileDiff::from_pixels(vec![((1, 1).into(), red), ((1, 1).into(), BLACK)]);
+        let mut canvas = Canvas::<TestImage>::new(Size::new(4, 4));
+        diff.apply(&mut canvas);
+
+        assert_eq!(canvas.pixel((1, 1).into()), red);
+    }
+
+    #[test]
+    fn apply_and_reverse() {
+        let mut canvas = Canvas::<TestImage>::new(Size::new(40, 20));
+        canvas.set_pixel((35, 19).into(), BLACK);
+        let pixels = (0..40).map(|i| ((i, 19).into(), BLACK)).collect();
+        let diff = TileDiff::from_pixels(pixels);
+        assert_eq!(diff.0.len(), 3);
+
+        let reverse = diff.apply(&mut canvas);
+        for i in 0..40 {
+            assert_eq!(canvas.pixel((i, 19).into()), BLACK);
+        }
+
+        reverse.apply(&mut canvas);
+        for i in 0..40 {
+            let color = if i == 35 { BLACK } else { TRANSPARENT };
+            assert_eq!(canvas.pixel((i, 19).into()), color);
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        let area = Rect::new(0, 0, 64, 64);
+        let pixels = vec![((3, 20).into(), BLACK), ((17, 18).into(), BLACK)];
+        let diff = TileDiff::from_pixels(pixels);
+        assert_eq!(diff.bounds(area), Some(Rect::new(3, 18, 15, 3)));
+
+        // A run that wraps around to the next row of the tile
+        let pixels = vec![((15, 0).into(), BLACK), ((0, 1).into(), BLACK)];
+        let diff = TileDiff::from_pixels(pixels);
+        assert_eq!(diff.bounds(area), Some(Rect::new(0, 0, 16, 2)));
+
+        assert_eq!(TileDiff::default().bounds(area), None);
+    }
+
+    #[test]
+    fn img_outside() {
+        let mut img = TestImage::new(Size::new(3, 3), TRANSPARENT);
+        img.set_pixel((0, 0).into(), BLACK);
+        img.set_pixel((2, 0).into(), BLACK);
+        img.set_pixel((2, 2).into(), BLACK);
+
+        let diff = TileDiff::from_img_outside(&img, Size::new(2, 2));
+        let mut canvas = Canvas::<TestImage>::new(Size::new(3, 3));
+        diff.apply(&mut canvas);
+
+        assert_eq!(canvas.pixel((0, 0).into()), TRANSPARENT);
+        assert_eq!(canvas.pixel((2, 0).into()), BLACK);
+        assert_eq!(canvas.pixel((2, 2).into()), BLACK);
+    }
+}
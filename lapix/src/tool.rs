@@ -13,6 +13,20 @@ pub enum Tool {
     Move,
     Rectangle,
     Ellipse,
+    Gradient,
+}
+
+/// Settings of the bucket tool
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BucketOptions {
+    /// How different the colors of the filled pixels can be from the color of
+    /// the clicked pixel, from 0 (only the same color) to 100 (any color)
+    pub tolerance: u8,
+    /// Fill every pixel of the canvas with a matching color, not only the area
+    /// around the clicked pixel
+    pub replace_all: bool,
+    /// Also fill through pixels that only touch diagonally
+    pub diagonal: bool,
 }
 
 impl Display for Tool {
@@ -27,6 +41,7 @@ impl Display for Tool {
             Self::Move => "move",
             Self::Rectangle => "rectangle",
             Self::Ellipse => "ellipse",
+            Self::Gradient => "gradient",
         };
 
         f.write_str(st)
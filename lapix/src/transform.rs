@@ -37,21 +37,29 @@ impl Transform {
             for j in 0..image.height() {
                 let p = (i, j).into();
                 let color = image.pixel(p);
+                let index = Self::nearest_color(palette, color);
+                image.set_pixel(p, palette[index]);
+            }
+        }
+    }
 
-                let mut min_dist = f32::MAX;
-                let mut min_index = 0;
-                for (i, palette_color) in palette.iter().enumerate() {
-                    let colorf: ColorF32 = (*palette_color).into();
-                    let dist = colorf.dist(&color.into());
+    /// Get the index of the color of a (non-empty) palette that is closest to
+    /// a color
+    pub(crate) fn nearest_color(palette: &[Color], color: Color) -> usize {
+        let color: ColorF32 = color.into();
+        let mut min_dist = f32::MAX;
+        let mut min_index = 0;
 
-                    if dist < min_dist {
-                        min_dist = dist;
-                        min_index = i;
-                    }
-                }
+        for (i, palette_color) in palette.iter().enumerate() {
+            let colorf: ColorF32 = (*palette_color).into();
+            let dist = colorf.dist(&color);
 
-                image.set_pixel(p, palette[min_index]);
+            if dist < min_dist {
+                min_dist = dist;
+                min_index = i;
             }
         }
+
+        min_index
     }
 }
@@ -4,11 +4,11 @@ use std::fmt::Debug;
 use std::path::PathBuf;
 
 /// Holds a function that takes a path as input and outputs the bytes of the
-/// project file found at that path.
-pub struct LoadProject(pub fn(PathBuf) -> Vec<u8>);
+/// project file found at that path, or an error if the file can't be read.
+pub struct LoadProject(pub fn(PathBuf) -> Result<Vec<u8>>);
 impl Debug for LoadProject {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.write_str("LoadProject(fn(PathBuf) -> Vec<u8>>)")
+        f.write_str("LoadProject(fn(PathBuf) -> Result<Vec<u8>>)")
     }
 }
 impl PartialEq for LoadProject {
@@ -23,17 +23,18 @@ impl Clone for LoadProject {
     }
 }
 
-impl From<fn(PathBuf) -> Vec<u8>> for LoadProject {
-    fn from(val: fn(PathBuf) -> Vec<u8>) -> Self {
+impl From<fn(PathBuf) -> Result<Vec<u8>>> for LoadProject {
+    fn from(val: fn(PathBuf) -> Result<Vec<u8>>) -> Self {
         Self(val)
     }
 }
 /// Holds a function that takes a path and a set of bytes as input as saves
-/// those bytes as a project file at that path
-pub struct SaveProject(pub fn(PathBuf, Vec<u8>));
+/// those bytes as a project file at that path, returning an error if the file
+/// can't be written
+pub struct SaveProject(pub fn(PathBuf, Vec<u8>) -> Result<()>);
 impl Debug for SaveProject {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.write_str("SaveProject(fn(PathBuf, Vec<u8>))")
+        f.write_str("SaveProject(fn(PathBuf, Vec<u8>) -> Result<()>)")
     }
 }
 
@@ -71,6 +72,29 @@ pub fn img_from_raw<IMG: Bitmap>(raw: image::RgbaImage) -> IMG {
     img
 }
 
+/// Scale an image up by an integer factor, repeating each pixel (nearest
+/// neighbour), so that pixel art stays crisp
+pub fn scale_image<IMG: Bitmap>(img: &IMG, scale: u8) -> IMG {
+    let scale = scale as usize;
+    let row_len = img.width() as usize * 4;
+    let mut bytes = Vec::with_capacity(img.bytes().len() * scale * scale);
+
+    for row in img.bytes().chunks(row_len) {
+        let mut scaled_row = Vec::with_capacity(row_len * scale);
+        for pixel in row.chunks(4) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            bytes.extend_from_slice(&scaled_row);
+        }
+    }
+
+    let size = (img.width() * scale as i32, img.height() * scale as i32).into();
+    IMG::from_parts(size, &bytes)
+}
+
 /// Save an image to the specified file path
 pub fn save_image<IMG: Bitmap>(bitmap: IMG, path: &str) -> Result<()> {
     let bytes = bitmap.bytes();
@@ -95,3 +119,22 @@ pub fn save_image<IMG: Bitmap>(bitmap: IMG, path: &str) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::TestImage;
+    use crate::Point;
+
+    #[test]
+    fn scale() {
+        let mut img = TestImage::new((2, 1).into(), color::TRANSPARENT);
+        img.set_pixel(Point::new(1, 0), color::BLACK);
+
+        let scaled = scale_image(&img, 3);
+        assert_eq!(scaled.size(), (6, 3).into());
+        assert_eq!(scaled.pixel(Point::new(2, 2)), color::TRANSPARENT);
+        assert_eq!(scaled.pixel(Point::new(3, 0)), color::BLACK);
+        assert_eq!(scaled.pixel(Point::new(5, 2)), color::BLACK);
+    }
+}
@@ -2,7 +2,14 @@
 use lapix::TestImage;
 
 use lapix::color::{BLACK, TRANSPARENT};
-use lapix::{Color, Event, Point, Size, State};
+use lapix::{
+    project, AtlasLayout, Bitmap, BucketOptions, CanvasEffect, Color, ColorMode, DirtyRect, Dither,
+    Error, Event, ExportMode, GradientOptions, GradientShape, Journal, LoadProject, Point, Rect,
+    RgbaImage, SaveProject, Size, State, UndoLimit,
+};
+use std::path::PathBuf;
+#[cfg(feature = "test-utils")]
+use test_case::test_case;
 
 #[cfg(feature = "test-utils")]
 #[test]
@@ -101,3 +108,1139 @@ fn bucket_then_erase() {
         }
     }
 }
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn draw_line_undo_then_redo() {
+    let side = 10;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(side - 1, side - 1)));
+    state.execute(Event::Undo);
+
+    for i in 0..side {
+        for j in 0..side {
+            assert_eq!(state.canvas().pixel(Point::new(i, j)), TRANSPARENT);
+        }
+    }
+
+    state.execute(Event::Redo);
+
+    for i in 0..side {
+        for j in 0..side {
+            let color = if i == j { BLACK } else { TRANSPARENT };
+            assert_eq!(state.canvas().pixel(Point::new(i, j)), color);
+        }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn new_action_clears_redo() {
+    let side = 10;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(side - 1, side - 1)));
+    state.execute(Event::Undo);
+    state.execute(Event::Bucket(Point::new(0, 0)));
+    state.execute(Event::Redo);
+
+    for i in 0..side {
+        for j in 0..side {
+            assert_eq!(state.canvas().pixel(Point::new(i, j)), BLACK);
+        }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn failed_or_no_op_event_keeps_redo() {
+    let side = 10;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(side - 1, side - 1)));
+    state.execute(Event::Undo);
+
+    let result = state.execute(Event::EditPaletteColor(99, BLACK));
+    assert!(matches!(result, Err(Error::InvalidPaletteIndex(99))));
+    state.execute(Event::SetMainColor(BLACK)).unwrap();
+    state.execute(Event::Redo);
+
+    assert_eq!(state.canvas().pixel(Point::new(3, 3)), BLACK);
+    assert_eq!(state.canvas().pixel(Point::new(3, 4)), TRANSPARENT);
+}
+
+#[cfg(feature = "test-utils")]
+type Snapshot = (Vec<(bool, u8, Vec<u8>)>, usize, Color, Vec<Color>);
+
+#[cfg(feature = "test-utils")]
+fn snapshot(state: &State<TestImage>) -> Snapshot {
+    let layers = state.layers();
+    let layers_data = (0..layers.count())
+        .map(|i| {
+            let layer = layers.get(i);
+            (
+                layer.visible(),
+                layer.opacity(),
+                layer.canvas().bytes().to_vec(),
+            )
+        })
+        .collect();
+
+    (
+        layers_data,
+        layers.active_index(),
+        state.main_color(),
+        state.palette().to_vec(),
+    )
+}
+
+#[cfg(feature = "test-utils")]
+fn two_layer_state() -> State<TestImage> {
+    let side = 10;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(side - 1, 0)));
+    state.execute(Event::NewLayerAbove);
+    state.execute(Event::SwitchLayer(1));
+    state.execute(Event::SetMainColor(Color::new(255, 0, 0, 255)));
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(0, side - 1)));
+
+    state
+}
+
+#[cfg(feature = "test-utils")]
+#[test_case(Event::SwitchLayer(0))]
+#[test_case(Event::ChangeLayerVisibility(0, false))]
+#[test_case(Event::ChangeLayerOpacity(1, 127))]
+#[test_case(Event::MoveLayerUp(0))]
+#[test_case(Event::MoveLayerDown(1))]
+#[test_case(Event::SetMainColor(Color::new(0, 255, 0, 255)))]
+#[test_case(Event::AddToPalette(Color::new(1, 2, 3, 255)))]
+#[test_case(Event::RemoveFromPalette(BLACK))]
+#[test_case(Event::ClearCanvas)]
+#[test_case(Event::ResizeCanvas(Size::new(4, 4)))]
+#[test_case(Event::ResizeCanvas(Size::new(12, 8)))]
+fn event_then_undo(event: Event) {
+    let mut state = two_layer_state();
+    let before = snapshot(&state);
+
+    state.execute(event);
+    assert_ne!(snapshot(&state), before);
+
+    state.execute(Event::Undo);
+    assert_eq!(snapshot(&state), before);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn load_palette_then_undo() {
+    let path = std::env::temp_dir().join("lapix_load_palette_then_undo.png");
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::Bucket(Point::new(0, 0)));
+    state.execute(Event::Save(path.clone()));
+
+    let mut state = two_layer_state();
+    let before = snapshot(&state);
+
+    state.execute(Event::LoadPalette(path.clone()));
+    assert_eq!(state.palette(), &[BLACK]);
+
+    state.execute(Event::Undo);
+    assert_eq!(snapshot(&state), before);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test_case(Event::ClearCanvas)]
+#[test_case(Event::ResizeCanvas(Size::new(4, 4)))]
+fn event_undo_then_redo(event: Event) {
+    let mut state = two_layer_state();
+    state.execute(event);
+    let after = snapshot(&state);
+
+    state.execute(Event::Undo);
+    state.execute(Event::Redo);
+    assert_eq!(snapshot(&state), after);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn undo_limit_steps() {
+    let side = 10;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.set_undo_limit(UndoLimit {
+        max_steps: Some(2),
+        max_bytes: None,
+    });
+
+    for i in 0..3 {
+        state.execute(Event::LineStart(Point::new(0, i)));
+        state.execute(Event::LineEnd(Point::new(side - 1, i)));
+    }
+    for _ in 0..3 {
+        state.execute(Event::Undo);
+    }
+
+    for i in 0..side {
+        for j in 0..side {
+            let color = if j == 0 { BLACK } else { TRANSPARENT };
+
+            assert_eq!(state.canvas().pixel(Point::new(i, j)), color);
+        }
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn undo_limit_bytes() {
+    let side = 64;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::Bucket(Point::new(0, 0)));
+    let one_step = state.undo_memory_size();
+    assert!(one_step > 0);
+
+    state.set_undo_limit(UndoLimit {
+        max_steps: None,
+        max_bytes: Some(one_step * 3 / 2),
+    });
+    state.execute(Event::ClearCanvas);
+    assert!(state.undo_memory_size() <= one_step * 3 / 2);
+
+    state.execute(Event::Undo);
+    state.execute(Event::Undo);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), BLACK);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn bucket_undo_is_compact() {
+    let side = 64;
+    let mut state = State::<TestImage>::new(Size::new(side, side), None, None);
+    state.execute(Event::Bucket(Point::new(0, 0)));
+
+    // A filled area should take far less than one byte per pixel
+    assert!(state.undo_memory_size() < (side * side) as usize);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn journal_sequence_numbers() {
+    let mut state = State::<TestImage>::new(Size::new(4, 4), None, None);
+    state.execute(Event::Bucket(Point::new(0, 0)));
+    // Repeated event is not accepted, so it is not recorded
+    state.execute(Event::Bucket(Point::new(0, 0)));
+    state.execute(Event::Undo);
+    state.execute(Event::Redo);
+
+    let entries = state.journal().entries();
+    let seqs: Vec<_> = entries.iter().map(|e| e.seq).collect();
+    assert_eq!(seqs, vec![0, 1, 2]);
+    assert_eq!(entries[0].event, Event::Bucket(Point::new(0, 0)));
+    assert_eq!(entries[2].event, Event::Redo);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn journal_replay_is_identical() {
+    let mut state = two_layer_state();
+    state.execute(Event::ChangeLayerOpacity(0, 100));
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(3, 3)));
+    state.execute(Event::BrushStroke(Point::new(6, 8)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::Undo);
+    state.execute(Event::ResizeCanvas(Size::new(8, 12)));
+    state.execute(Event::StartSelection(Point::new(1, 1)));
+    state.execute(Event::EndSelection(Point::new(4, 4)));
+
+    let bytes = bincode::serialize(state.journal()).unwrap();
+    let journal: Journal = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(&journal, state.journal());
+
+    journal.verify(&state).unwrap();
+    let replayed = journal.replay::<TestImage>().unwrap();
+    assert_eq!(snapshot(&replayed), snapshot(&state));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn journal_replay_mismatch() {
+    let mut state = two_layer_state();
+    let journal = state.journal().clone();
+    state.execute(Event::Bucket(Point::new(5, 5)));
+
+    assert!(matches!(
+        journal.verify(&state),
+        Err(Error::ReplayMismatch(_))
+    ));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn journal_replay_settings() {
+    let mut state = State::<TestImage>::new(Size::new(4, 4), None, None);
+    let limit = UndoLimit {
+        max_steps: Some(2),
+        max_bytes: None,
+    };
+    state.set_undo_limit(limit);
+    for x in 0..4 {
+        state.execute(Event::LineStart(Point::new(x, 0)));
+        state.execute(Event::LineEnd(Point::new(x, 3)));
+    }
+    // Raising the limit midway keeps the history trimmed by the earlier limit
+    state.execute(Event::SetUndoLimit(UndoLimit::default()));
+    state.execute(Event::LineStart(Point::new(0, 1)));
+    state.execute(Event::LineEnd(Point::new(3, 1)));
+    assert!(matches!(
+        state.journal().entries()[0].event,
+        Event::SetUndoLimit(l) if l == limit
+    ));
+    state.journal().verify(&state).unwrap();
+    let mut replayed = state.journal().replay::<TestImage>().unwrap();
+    for _ in 0..4 {
+        replayed.execute(Event::Undo);
+    }
+    assert_eq!(replayed.canvas().pixel(Point::new(1, 0)), BLACK);
+    assert_eq!(replayed.canvas().pixel(Point::new(2, 0)), TRANSPARENT);
+
+    for event in [
+        Event::SetColorMode(ColorMode::Indexed),
+        Event::SetSecondaryColor(BLACK),
+        Event::SetBucketOptions(BucketOptions {
+            diagonal: true,
+            ..Default::default()
+        }),
+        Event::SetGradientOptions(GradientOptions {
+            dither: Dither::Bayer2,
+            ..Default::default()
+        }),
+    ] {
+        let journal = state.journal().clone();
+        state.execute(event).unwrap();
+        assert!(matches!(
+            journal.verify(&state),
+            Err(Error::ReplayMismatch(_))
+        ));
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn line_reports_dirty_rect() {
+    let mut state = two_layer_state();
+    state.execute(Event::LineStart(Point::new(2, 3)));
+    let effect = state.execute(Event::LineEnd(Point::new(5, 7))).unwrap();
+
+    let dirty = DirtyRect::new(1, Rect::new(2, 3, 4, 5));
+    assert_eq!(effect, CanvasEffect::Pixels(dirty.clone()));
+
+    assert_eq!(
+        state.execute(Event::Undo).unwrap(),
+        CanvasEffect::Pixels(dirty.clone())
+    );
+    assert_eq!(
+        state.execute(Event::Redo).unwrap(),
+        CanvasEffect::Pixels(dirty)
+    );
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn brush_reports_dirty_rect() {
+    let mut state = State::<TestImage>::new(Size::new(10, 10), None, None);
+    state.execute(Event::BrushStart);
+    let effect = state.execute(Event::BrushStroke(Point::new(4, 4))).unwrap();
+    assert_eq!(
+        effect,
+        CanvasEffect::Pixels(DirtyRect::new(0, Rect::new(4, 4, 1, 1)))
+    );
+
+    let effect = state.execute(Event::BrushStroke(Point::new(6, 5))).unwrap();
+    assert_eq!(
+        effect,
+        CanvasEffect::Pixels(DirtyRect::new(0, Rect::new(5, 4, 2, 2)))
+    );
+}
+
+#[cfg(feature = "test-utils")]
+fn project_state() -> State<TestImage> {
+    let load_fn = Some(LoadProject(project::load));
+    let save_fn = Some(SaveProject(project::save));
+    let mut state = State::<TestImage>::new(Size::new(8, 8), load_fn, save_fn);
+    state.execute(Event::LineStart(Point::new(0, 0)));
+    state.execute(Event::LineEnd(Point::new(7, 7)));
+
+    state
+}
+
+#[cfg(feature = "test-utils")]
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lapix-{}-{name}", std::process::id()))
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn save_and_load_project() {
+    let path = temp_path("save_and_load.tarsila");
+    let mut state = project_state();
+    state.execute(Event::SaveProject(path.clone())).unwrap();
+
+    let mut loaded = project_state();
+    loaded.execute(Event::ClearCanvas);
+    loaded.execute(Event::LoadProject(path.clone())).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.canvas().pixel(Point::new(3, 3)), BLACK);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn save_and_load_project_archive() {
+    let path = temp_path("save_and_load.zip");
+    let mut state = project_state();
+    state
+        .execute(Event::SaveProjectArchive(path.clone()))
+        .unwrap();
+
+    let mut loaded = project_state();
+    loaded.execute(Event::ClearCanvas);
+    loaded
+        .execute(Event::LoadProjectArchive(path.clone()))
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.canvas().pixel(Point::new(3, 3)), BLACK);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn missing_project_fn() {
+    let mut state = State::<TestImage>::new(Size::new(8, 8), None, None);
+    let path = temp_path("missing_fn.tarsila");
+
+    assert!(matches!(
+        state.execute(Event::SaveProject(path.clone())),
+        Err(Error::MissingSaveProjectFn)
+    ));
+    assert!(matches!(
+        state.execute(Event::LoadProject(path)),
+        Err(Error::MissingLoadProjectFn)
+    ));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn save_project_to_invalid_path() {
+    let mut state = project_state();
+    let path = temp_path("no_such_dir").join("project.tarsila");
+
+    assert!(matches!(
+        state.execute(Event::SaveProject(path)),
+        Err(Error::SaveProjectFailed(..))
+    ));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn failed_project_load_keeps_state() {
+    let mut state = project_state();
+    let missing = temp_path("missing.tarsila");
+    assert!(matches!(
+        state.execute(Event::LoadProject(missing)),
+        Err(Error::LoadProjectFailed(..))
+    ));
+
+    let invalid = temp_path("invalid.tarsila");
+    std::fs::write(&invalid, b"not a project").unwrap();
+    let result = state.execute(Event::LoadProject(invalid.clone()));
+    assert!(matches!(result, Err(Error::InvalidProjectHeader)));
+
+    let mut bytes = b"tarsila 0.2.0 format 2 ".to_vec();
+    bytes.extend([0xfa, 0x1a, 0xfe, 0x1b, 0xee]);
+    bytes.extend(b"not a project");
+    std::fs::write(&invalid, bytes).unwrap();
+    let result = state.execute(Event::LoadProject(invalid.clone()));
+    std::fs::remove_file(invalid).unwrap();
+    assert!(matches!(result, Err(Error::InvalidProjectFile(..))));
+
+    assert_eq!(state.canvas().pixel(Point::new(3, 3)), BLACK);
+
+    // The project functions are still there after the failures
+    let path = temp_path("after_failure.tarsila");
+    state.execute(Event::SaveProject(path.clone())).unwrap();
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn newer_project_version() {
+    let mut state = project_state();
+    let path = temp_path("newer.tarsila");
+    let mut bytes = b"tarsila 9.0.0 format 99 ".to_vec();
+    bytes.extend([0xfa, 0x1a, 0xfe, 0x1b, 0xee]);
+    std::fs::write(&path, bytes).unwrap();
+
+    let result = state.execute(Event::LoadProject(path.clone()));
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(result, Err(Error::NewerProjectVersion(99))));
+}
+
+#[test]
+fn load_v1_project() {
+    let load_fn = Some(LoadProject(project::load));
+    let mut state = State::<RgbaImage>::new(Size::new(1, 1), load_fn, None);
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/v1.tarsila");
+    state.execute(Event::LoadProject(path)).unwrap();
+
+    let (red, blue) = (Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255));
+    let layers = state.layers();
+    assert_eq!(layers.count(), 2);
+    assert_eq!(layers.active_index(), 1);
+    assert_eq!(layers.canvas_at(0).size(), Size::new(4, 4));
+    assert_eq!(layers.canvas_at(0).pixel(Point::new(1, 1)), red);
+    assert_eq!(layers.canvas_at(1).pixel(Point::new(2, 3)), blue);
+    assert_eq!(layers.canvas_at(1).pixel(Point::new(1, 1)), TRANSPARENT);
+    assert_eq!(layers.get(1).opacity(), 128);
+    assert!(layers.get(1).visible());
+    assert_eq!(state.main_color(), Color::new(0, 255, 0, 255));
+    assert_eq!(state.spritesheet(), Size::new(2, 2));
+    assert!(state.palette().contains(&Color::new(1, 2, 3, 255)));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn export_scaled_image() {
+    let path = temp_path("export_scaled.png");
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(1, 0)));
+    state.execute(Event::BrushEnd);
+
+    let scale = 4;
+    state.execute(Event::Export {
+        path: path.clone(),
+        scale,
+        mode: ExportMode::VisibleLayers,
+        indexed: false,
+    });
+
+    let img = RgbaImage::try_from_file_bytes(std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(img.size(), Size::new(8, 4));
+    assert_eq!(img.pixel(Point::new(3, 3)), TRANSPARENT);
+    assert_eq!(img.pixel(Point::new(4, 0)), BLACK);
+    assert_eq!(img.pixel(Point::new(7, 3)), BLACK);
+
+    let result = state.execute(Event::Export {
+        path: path.clone(),
+        scale: 0,
+        mode: ExportMode::VisibleLayers,
+        indexed: false,
+    });
+    assert!(matches!(result, Err(Error::InvalidExportScale)));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn export_layers() {
+    let path = temp_path("export_layers.png");
+    let red = Color::new(255, 0, 0, 255);
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(0, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::NewLayerAbove);
+    state.execute(Event::SwitchLayer(1));
+    state.execute(Event::SetMainColor(red));
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(1, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::ChangeLayerVisibility(1, false));
+
+    let read = |path: &PathBuf| {
+        let img = RgbaImage::try_from_file_bytes(std::fs::read(path).unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        (img.pixel(Point::new(0, 0)), img.pixel(Point::new(1, 0)))
+    };
+    let export = |state: &mut State<TestImage>, mode| {
+        state.execute(Event::Export {
+            path: path.clone(),
+            scale: 1,
+            mode,
+            indexed: false,
+        })
+    };
+
+    export(&mut state, ExportMode::VisibleLayers).unwrap();
+    assert_eq!(read(&path), (BLACK, TRANSPARENT));
+    export(&mut state, ExportMode::AllLayers).unwrap();
+    assert_eq!(read(&path), (BLACK, red));
+    export(&mut state, ExportMode::Layers(vec![1])).unwrap();
+    assert_eq!(read(&path), (TRANSPARENT, red));
+
+    export(&mut state, ExportMode::EachLayer).unwrap();
+    assert_eq!(
+        read(&temp_path("export_layers_layer0.png")),
+        (BLACK, TRANSPARENT)
+    );
+    assert_eq!(
+        read(&temp_path("export_layers_layer1.png")),
+        (TRANSPARENT, red)
+    );
+
+    let result = export(&mut state, ExportMode::Layers(vec![0, 2]));
+    assert!(matches!(result, Err(Error::LayerNotFound(2))));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn export_atlas() {
+    let path = temp_path("export_atlas.png");
+    let mut state = State::<TestImage>::new(Size::new(4, 2), None, None);
+    state.execute(Event::SetSpritesheet(Size::new(2, 1)));
+
+    state.execute(Event::ExportAtlas {
+        path: path.clone(),
+        layout: AtlasLayout::Array,
+        frame_delay: 150,
+    });
+
+    let img = RgbaImage::try_from_file_bytes(std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(img.size(), Size::new(4, 2));
+
+    let json_path = path.with_extension("json");
+    let json: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&json_path).unwrap()).unwrap();
+    let frames = json["frames"].as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1]["frame"]["x"], 2);
+    assert_eq!(frames[1]["duration"], 150);
+    assert_eq!(json["meta"]["size"]["w"], 4);
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(json_path).unwrap();
+}
+
+/// Save an image of a certain size, with a single pixel painted, to a
+/// temporary file
+#[cfg(feature = "test-utils")]
+fn save_frame(name: &str, size: Size<i32>, p: Point<i32>, color: Color) -> PathBuf {
+    let path = temp_path(name);
+    let mut state = State::<TestImage>::new(size, None, None);
+    state.execute(Event::SetMainColor(color));
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(p));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::Save(path.clone()));
+
+    path
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn import_frames() {
+    let red = Color::new(255, 0, 0, 255);
+    let paths: Vec<_> = (0..3)
+        .map(|i| {
+            save_frame(
+                &format!("frame{i}.png"),
+                Size::new(2, 2),
+                Point::new(i % 2, 1),
+                red,
+            )
+        })
+        .collect();
+    let mut state = State::<TestImage>::new(Size::new(3, 3), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(2, 2)));
+    state.execute(Event::BrushEnd);
+
+    state.execute(Event::ImportFrames {
+        paths: paths.clone(),
+        columns: 2,
+    });
+
+    assert_eq!(state.canvas().size(), Size::new(4, 4));
+    assert_eq!(state.spritesheet(), Size::new(2, 2));
+    assert_eq!(state.canvas().pixel(Point::new(0, 1)), red);
+    assert_eq!(state.canvas().pixel(Point::new(3, 1)), red);
+    assert_eq!(state.canvas().pixel(Point::new(0, 3)), red);
+    assert_eq!(state.canvas().pixel(Point::new(2, 2)), TRANSPARENT);
+    let painted = (0..4)
+        .flat_map(|x| (0..4).map(move |y| Point::new(x, y)))
+        .filter(|&p| state.canvas().pixel(p) != TRANSPARENT)
+        .count();
+    assert_eq!(painted, 3);
+
+    // Everything is undone at once
+    state.execute(Event::Undo);
+    assert_eq!(state.canvas().size(), Size::new(3, 3));
+    assert_eq!(state.spritesheet(), Size::new(1, 1));
+    assert_eq!(state.canvas().pixel(Point::new(2, 2)), BLACK);
+    assert_eq!(state.canvas().pixel(Point::new(0, 1)), TRANSPARENT);
+
+    state.execute(Event::Redo);
+    assert_eq!(state.spritesheet(), Size::new(2, 2));
+    assert_eq!(state.canvas().pixel(Point::new(3, 1)), red);
+
+    for path in paths {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn import_frames_partial_grid() {
+    let red = Color::new(255, 0, 0, 255);
+    let paths: Vec<_> = (0..5)
+        .map(|i| {
+            save_frame(
+                &format!("partial_frame{i}.png"),
+                Size::new(2, 2),
+                Point::ZERO,
+                red,
+            )
+        })
+        .collect();
+    let mut state = State::<TestImage>::new(Size::new(3, 3), None, None);
+    state.execute(Event::NewLayerAbove);
+    state.execute(Event::SwitchLayer(1));
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(2, 2)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::SwitchLayer(0));
+
+    state.execute(Event::ImportFrames {
+        paths: paths.clone(),
+        columns: 2,
+    });
+
+    assert_eq!(state.spritesheet(), Size::new(2, 3));
+    for i in 0..2 {
+        assert_eq!(state.layers().get(i).canvas().size(), Size::new(4, 6));
+    }
+    assert_eq!(state.canvas().pixel(Point::new(0, 4)), red);
+    // The last cell of the grid is empty
+    assert_eq!(state.canvas().pixel(Point::new(2, 4)), TRANSPARENT);
+    // The other layer keeps its contents
+    let other = state.layers().get(1).canvas();
+    assert_eq!(other.pixel(Point::new(2, 2)), BLACK);
+
+    state.execute(Event::Undo);
+    assert_eq!(state.spritesheet(), Size::new(1, 1));
+    for i in 0..2 {
+        assert_eq!(state.layers().get(i).canvas().size(), Size::new(3, 3));
+    }
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), TRANSPARENT);
+    assert_eq!(
+        state.layers().get(1).canvas().pixel(Point::new(2, 2)),
+        BLACK
+    );
+
+    for path in paths {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn import_frames_errors() {
+    let small = save_frame("small_frame.png", Size::new(2, 2), Point::ZERO, BLACK);
+    let big = save_frame("big_frame.png", Size::new(3, 2), Point::ZERO, BLACK);
+    let mut state = State::<TestImage>::new(Size::new(3, 3), None, None);
+
+    let result = state.execute(Event::ImportFrames {
+        paths: vec![small.clone(), big.clone()],
+        columns: 2,
+    });
+    assert!(matches!(
+        result,
+        Err(Error::FrameSizeMismatch(path, found, expected))
+            if path == big && found == Size::new(3, 2) && expected == Size::new(2, 2)
+    ));
+
+    let result = state.execute(Event::ImportFrames {
+        paths: vec![small.clone()],
+        columns: 0,
+    });
+    assert!(matches!(result, Err(Error::InvalidFrameGrid(1, 0))));
+
+    let result = state.execute(Event::ImportFrames {
+        paths: Vec::new(),
+        columns: 1,
+    });
+    assert!(matches!(result, Err(Error::NoFrames)));
+
+    assert_eq!(state.canvas().size(), Size::new(3, 3));
+    std::fs::remove_file(small).unwrap();
+    std::fs::remove_file(big).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn load_palette_file() {
+    let path = temp_path("palette.gpl");
+    std::fs::write(&path, "GIMP Palette\n#\n 16  32  48\tNight\n255 255 255\n").unwrap();
+    let mut state = State::<TestImage>::new(Size::new(2, 2), None, None);
+
+    state.execute(Event::LoadPalette(path.clone()));
+
+    assert_eq!(
+        state.palette(),
+        [Color::new(16, 32, 48, 255), Color::new(255, 255, 255, 255)]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test_case("saved_palette.png")]
+#[test_case("saved_palette.gpl")]
+#[test_case("saved_palette.pal")]
+#[test_case("saved_palette.hex")]
+#[test_case("saved_palette.txt")]
+fn save_palette(name: &str) {
+    let path = temp_path(name);
+    let mut state = State::<TestImage>::new(Size::new(2, 2), None, None);
+    state.execute(Event::AddToPalette(Color::new(12, 34, 56, 255)));
+
+    state.execute(Event::SavePalette(path.clone()));
+    let mut loaded = State::<TestImage>::new(Size::new(2, 2), None, None);
+    loaded.execute(Event::LoadPalette(path.clone()));
+
+    let mut expected = state.palette().to_vec();
+    let mut colors = loaded.palette().to_vec();
+    // Palettes loaded from images are sorted by hue
+    if name.ends_with(".png") {
+        let key = |c: &Color| (c.r, c.g, c.b, c.a);
+        expected.sort_by_key(key);
+        colors.sort_by_key(key);
+    }
+    assert_eq!(colors, expected);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn builtin_palette() {
+    let mut state = State::<TestImage>::new(Size::new(2, 2), None, None);
+    let default = state.palette().to_vec();
+
+    state.execute(Event::SetBuiltinPalette("Game Boy".to_owned()));
+    assert_eq!(state.palette().len(), 4);
+    assert_eq!(state.palette()[0], Color::new(0x0f, 0x38, 0x0f, 255));
+
+    state.execute(Event::Undo);
+    assert_eq!(state.palette(), default);
+
+    let result = state.execute(Event::SetBuiltinPalette("Game Girl".to_owned()));
+    assert!(matches!(result, Err(Error::UnknownPalette(_))));
+    assert!(lapix::BUILTIN_PALETTES.contains(&"PICO-8"));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn indexed_color_mode() {
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(0, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::SetBuiltinPalette("Game Boy".to_owned()));
+
+    let darkest = Color::new(0x0f, 0x38, 0x0f, 255);
+    state.execute(Event::SetColorMode(ColorMode::Indexed));
+    assert_eq!(state.color_mode(), ColorMode::Indexed);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), darkest);
+    assert_eq!(state.canvas().pixel(Point::new(1, 0)), TRANSPARENT);
+    assert_eq!(state.main_color(), darkest);
+
+    let red = Color::new(255, 0, 0, 255);
+    state.execute(Event::EditPaletteColor(0, red));
+    assert_eq!(state.palette()[0], red);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), red);
+    assert_eq!(state.main_color(), red);
+
+    let result = state.execute(Event::EditPaletteColor(1, red));
+    assert!(matches!(result, Err(Error::DuplicatePaletteColor)));
+    let result = state.execute(Event::EditPaletteColor(4, red));
+    assert!(matches!(result, Err(Error::InvalidPaletteIndex(4))));
+
+    state.execute(Event::SetMainColor(Color::new(250, 10, 0, 255)));
+    assert_eq!(state.main_color(), red);
+
+    state.execute(Event::Undo);
+    assert_eq!(state.palette()[0], darkest);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), darkest);
+
+    state.execute(Event::Undo);
+    assert_eq!(state.color_mode(), ColorMode::Rgba);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), BLACK);
+    assert_eq!(state.main_color(), BLACK);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn edit_color_in_indexed_mode() {
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(0, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::SetBuiltinPalette("Game Boy".to_owned()));
+    state.execute(Event::SetColorMode(ColorMode::Indexed));
+
+    // The main color can't leave the palette, but the edited color can
+    let red = Color::new(255, 0, 0, 255);
+    state.execute(Event::SetMainColor(red));
+    assert_ne!(state.main_color(), red);
+
+    state.execute(Event::EditPaletteColor(0, red)).unwrap();
+    assert_eq!(state.palette()[0], red);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), red);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn repeated_palette_colors() {
+    let path = temp_path("repeated.gpl");
+    std::fs::write(
+        &path,
+        "GIMP Palette\n0 0 0\tInk\n255 255 255\n0 0 0\tShadow\n",
+    )
+    .unwrap();
+    let white = Color::new(255, 255, 255, 255);
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::LoadPalette(path.clone()));
+    assert_eq!(state.palette(), [BLACK, white, BLACK]);
+
+    // In RGBA mode, entries can share a color
+    state.execute(Event::EditPaletteColor(2, white)).unwrap();
+    assert_eq!(state.palette(), [BLACK, white, white]);
+    state.execute(Event::Undo);
+
+    // In indexed mode, they are merged
+    state.execute(Event::SetColorMode(ColorMode::Indexed));
+    assert_eq!(state.palette(), [BLACK, white]);
+    let result = state.execute(Event::EditPaletteColor(0, white));
+    assert!(matches!(result, Err(Error::DuplicatePaletteColor)));
+
+    state.execute(Event::SetBuiltinPalette("Game Boy".to_owned()));
+    state.execute(Event::LoadPalette(path.clone()));
+    assert_eq!(state.palette(), [BLACK, white]);
+
+    state.execute(Event::Undo);
+    state.execute(Event::Undo);
+    state.execute(Event::Undo);
+    assert_eq!(state.color_mode(), ColorMode::Rgba);
+    assert_eq!(state.palette(), [BLACK, white, BLACK]);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn save_and_load_indexed_project() {
+    let mut state = project_state();
+    state.execute(Event::SetBuiltinPalette("Game Boy".to_owned()));
+    state.execute(Event::SetColorMode(ColorMode::Indexed));
+    state.execute(Event::SetSecondaryColor(Color::new(1, 2, 3, 255)));
+    let bucket = BucketOptions {
+        tolerance: 30,
+        replace_all: true,
+        diagonal: false,
+    };
+    state.execute(Event::SetBucketOptions(bucket));
+    let gradient = GradientOptions {
+        shape: GradientShape::Radial,
+        dither: Dither::Bayer8,
+        along_palette: true,
+    };
+    state.execute(Event::SetGradientOptions(gradient));
+    let darkest = Color::new(0x0f, 0x38, 0x0f, 255);
+
+    let file = temp_path("indexed.tarsila");
+    state.execute(Event::SaveProject(file.clone())).unwrap();
+    let archive = temp_path("indexed.zip");
+    state
+        .execute(Event::SaveProjectArchive(archive.clone()))
+        .unwrap();
+
+    for event in [
+        Event::LoadProject(file.clone()),
+        Event::LoadProjectArchive(archive.clone()),
+    ] {
+        let mut loaded = project_state();
+        loaded.execute(event).unwrap();
+
+        assert_eq!(loaded.color_mode(), ColorMode::Indexed);
+        assert_eq!(loaded.palette(), state.palette());
+        assert_eq!(loaded.secondary_color(), Color::new(1, 2, 3, 255));
+        assert_eq!(loaded.bucket_options(), bucket);
+        assert_eq!(loaded.gradient_options(), gradient);
+        assert_eq!(loaded.canvas().pixel(Point::new(3, 3)), darkest);
+
+        // Editing the palette still recolors the image
+        let red = Color::new(255, 0, 0, 255);
+        loaded.execute(Event::EditPaletteColor(0, red)).unwrap();
+        assert_eq!(loaded.canvas().pixel(Point::new(3, 3)), red);
+    }
+    std::fs::remove_file(file).unwrap();
+    std::fs::remove_file(archive).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn export_indexed_image() {
+    let path = temp_path("export_indexed.png");
+    let mut state = State::<TestImage>::new(Size::new(2, 1), None, None);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(0, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::SetBuiltinPalette("PICO-8".to_owned()));
+
+    state
+        .execute(Event::Export {
+            path: path.clone(),
+            scale: 1,
+            mode: ExportMode::VisibleLayers,
+            indexed: true,
+        })
+        .unwrap();
+
+    let img = RgbaImage::try_from_file_bytes(std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(img.pixel(Point::new(0, 0)), BLACK);
+    assert_eq!(img.pixel(Point::new(1, 0)), TRANSPARENT);
+
+    let result = state.execute(Event::Export {
+        path: temp_path("export_indexed.jpg"),
+        scale: 1,
+        mode: ExportMode::VisibleLayers,
+        indexed: true,
+    });
+    assert!(matches!(result, Err(Error::UnsupportedImageFormat)));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn bucket_options() {
+    let mut state = State::<TestImage>::new(Size::new(3, 3), None, None);
+    let gray = Color::new(20, 20, 20, 255);
+    state.execute(Event::SetMainColor(gray));
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(0, 0)));
+    state.execute(Event::BrushEnd);
+    state.execute(Event::BrushStart);
+    state.execute(Event::BrushStroke(Point::new(2, 2)));
+    state.execute(Event::BrushEnd);
+
+    let options = BucketOptions {
+        tolerance: 10,
+        replace_all: true,
+        diagonal: false,
+    };
+    state.execute(Event::SetBucketOptions(options));
+    assert_eq!(state.bucket_options(), options);
+    state.execute(Event::SetMainColor(BLACK));
+    state.execute(Event::Bucket(Point::new(2, 2)));
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), BLACK);
+    assert_eq!(state.canvas().pixel(Point::new(2, 2)), BLACK);
+    assert_eq!(state.canvas().pixel(Point::new(1, 1)), TRANSPARENT);
+
+    state.execute(Event::Undo);
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), gray);
+    assert_eq!(state.canvas().pixel(Point::new(2, 2)), gray);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn gradient() {
+    let mut state = State::<TestImage>::new(Size::new(6, 2), None, None);
+    state.execute(Event::LineStart(Point::new(3, 0)));
+    state.execute(Event::LineEnd(Point::new(3, 1)));
+    let red = Color::new(255, 0, 0, 255);
+    let blue = Color::new(0, 0, 255, 255);
+    state.execute(Event::SetMainColor(red));
+    state.execute(Event::SetSecondaryColor(blue));
+    assert_eq!(state.secondary_color(), blue);
+
+    // Fills the area on the left of the line
+    state.execute(Event::GradientStart(Point::new(0, 0)));
+    state.execute(Event::GradientEnd(Point::new(2, 0)));
+    assert_eq!(state.canvas().pixel(Point::new(0, 1)), red);
+    assert_eq!(state.canvas().pixel(Point::new(2, 1)), blue);
+    assert_eq!(state.canvas().pixel(Point::new(3, 0)), BLACK);
+    assert_eq!(state.canvas().pixel(Point::new(4, 0)), TRANSPARENT);
+
+    state.execute(Event::Undo);
+    assert_eq!(state.canvas().pixel(Point::new(0, 1)), TRANSPARENT);
+    assert_eq!(state.canvas().pixel(Point::new(2, 1)), TRANSPARENT);
+
+    // Fills the selection, across the line
+    let options = GradientOptions {
+        shape: GradientShape::Radial,
+        dither: Dither::Bayer2,
+        along_palette: false,
+    };
+    state.execute(Event::SetGradientOptions(options));
+    assert_eq!(state.gradient_options(), options);
+    state.execute(Event::StartSelection(Point::new(3, 0)));
+    state.execute(Event::EndSelection(Point::new(5, 1)));
+    state.execute(Event::GradientStart(Point::new(5, 0)));
+    state.execute(Event::GradientEnd(Point::new(3, 0)));
+    assert_eq!(state.canvas().pixel(Point::new(5, 0)), red);
+    assert_eq!(state.canvas().pixel(Point::new(3, 1)), blue);
+    assert_eq!(state.canvas().pixel(Point::new(2, 0)), TRANSPARENT);
+
+    state.execute(Event::SetMainColor(blue));
+    let result = state.execute(Event::GradientEnd(Point::new(0, 0)));
+    assert!(matches!(result, Err(Error::DrawingNotStarted)));
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn gradient_uses_bucket_options() {
+    // Two diagonal lines split the transparent pixels into separate areas
+    let mut state = State::<TestImage>::new(Size::new(4, 4), None, None);
+    state.execute(Event::LineStart(Point::new(0, 1)));
+    state.execute(Event::LineEnd(Point::new(2, 3)));
+    state.execute(Event::LineStart(Point::new(1, 0)));
+    state.execute(Event::LineEnd(Point::new(3, 2)));
+    let red = Color::new(255, 0, 0, 255);
+    state.execute(Event::SetMainColor(red));
+    state.execute(Event::SetSecondaryColor(red));
+
+    state.execute(Event::GradientStart(Point::new(0, 0)));
+    state.execute(Event::GradientEnd(Point::new(1, 1)));
+    assert_eq!(state.canvas().pixel(Point::new(0, 0)), red);
+    assert_eq!(state.canvas().pixel(Point::new(1, 1)), TRANSPARENT);
+    state.execute(Event::Undo);
+
+    // Through the corners, the gradient reaches the pixels between the lines
+    state.execute(Event::SetBucketOptions(BucketOptions {
+        diagonal: true,
+        ..Default::default()
+    }));
+    state.execute(Event::GradientStart(Point::new(0, 0)));
+    state.execute(Event::GradientEnd(Point::new(1, 1)));
+    assert_eq!(state.canvas().pixel(Point::new(1, 1)), red);
+    assert_eq!(state.canvas().pixel(Point::new(3, 3)), red);
+    assert_eq!(state.canvas().pixel(Point::new(1, 2)), BLACK);
+    state.execute(Event::Undo);
+
+    // Every transparent pixel is filled, even without touching
+    state.execute(Event::SetBucketOptions(BucketOptions {
+        replace_all: true,
+        ..Default::default()
+    }));
+    state.execute(Event::GradientStart(Point::new(0, 0)));
+    state.execute(Event::GradientEnd(Point::new(1, 1)));
+    assert_eq!(state.canvas().pixel(Point::new(1, 1)), red);
+    assert_eq!(state.canvas().pixel(Point::new(3, 0)), red);
+    assert_eq!(state.canvas().pixel(Point::new(1, 2)), BLACK);
+}
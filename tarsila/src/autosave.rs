@@ -0,0 +1,383 @@
+use crate::wrapped_image::WrappedImage;
+use crate::Result;
+use lapix::{project, Event, State};
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+const DEFAULT_MAX_EVENTS: usize = 100;
+/// Extensions of the files kept for each instance: the recovery file itself,
+/// the lock and the path of the project file being autosaved
+const RECOVERY_EXT: &str = "tarsila";
+const LOCK_EXT: &str = "lock";
+const PROJECT_EXT: &str = "project";
+
+/// Autosave settings, read from environment variables:
+///
+/// * `TARSILA_AUTOSAVE_SECS`: seconds between autosaves (default 60);
+/// * `TARSILA_AUTOSAVE_EVENTS`: number of undoable events after which the
+///   project is autosaved, even if the interval hasn't passed (default 100);
+/// * `TARSILA_RECOVERY_DIR`: directory where the recovery files are kept
+///   (default `tarsila` in the temporary directory of the system).
+///
+/// Setting the interval or the number of events to 0 disables that trigger.
+pub struct AutosaveConfig {
+    pub interval: Option<Duration>,
+    pub max_events: Option<usize>,
+    pub dir: PathBuf,
+}
+
+impl AutosaveConfig {
+    pub fn from_env() -> Self {
+        let interval = env_number("TARSILA_AUTOSAVE_SECS", DEFAULT_INTERVAL_SECS);
+        let max_events = env_number("TARSILA_AUTOSAVE_EVENTS", DEFAULT_MAX_EVENTS as u64);
+        let dir = std::env::var_os("TARSILA_RECOVERY_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("tarsila"));
+
+        Self {
+            interval: (interval > 0).then_some(Duration::from_secs(interval)),
+            max_events: (max_events > 0).then_some(max_events as usize),
+            dir,
+        }
+    }
+}
+
+fn env_number(var: &str, default: u64) -> u64 {
+    match std::env::var(var) {
+        Ok(val) => val.parse().unwrap_or_else(|_| {
+            eprintln!("WARN: invalid value for {var}: {val}, using {default}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Periodically saves the project to a recovery file, so that work is not lost
+/// if the editor crashes.
+///
+/// Each running instance has its own recovery file, named after its process
+/// id, and holds a lock on a file with the same name, which the system
+/// releases when the process ends, even if it crashes. So the recovery files
+/// of other instances are only offered once nobody holds their lock.
+pub struct Autosave {
+    config: AutosaveConfig,
+    id: u32,
+    /// The lock of this instance, once it's taken
+    lock: Option<File>,
+    /// The project file last saved explicitly, if any
+    project: Option<PathBuf>,
+    last_save: Instant,
+    pending_events: usize,
+    paused: bool,
+}
+
+impl Autosave {
+    pub fn new(config: AutosaveConfig) -> Self {
+        Self::with_id(config, std::process::id())
+    }
+
+    fn with_id(config: AutosaveConfig, id: u32) -> Self {
+        Self {
+            config,
+            id,
+            lock: None,
+            project: None,
+            last_save: Instant::now(),
+            pending_events: 0,
+            paused: false,
+        }
+    }
+
+    /// The recovery file of this instance
+    pub fn recovery_path(&self) -> PathBuf {
+        self.path(self.id, RECOVERY_EXT)
+    }
+
+    fn path(&self, id: u32, ext: &str) -> PathBuf {
+        self.config.dir.join(format!("recovery-{id}.{ext}"))
+    }
+
+    /// Take the lock of this instance, if it's not held yet
+    fn hold_lock(&mut self) -> Result<()> {
+        if self.lock.is_none() {
+            std::fs::create_dir_all(&self.config.dir)?;
+            let file = File::create(self.path(self.id, LOCK_EXT))?;
+            file.try_lock().map_err(std::io::Error::from)?;
+            self.lock = Some(file);
+        }
+
+        Ok(())
+    }
+
+    /// Take over the most recent recovery file left by an instance that is no
+    /// longer running, moving it to the recovery file of this instance. Files
+    /// older than the project they were autosaved from are deleted instead.
+    /// Returns the path of the claimed file, if any.
+    pub fn claim_recovery(&mut self) -> Result<Option<PathBuf>> {
+        self.hold_lock()?;
+
+        let mut orphans = self.orphans();
+        orphans.sort_by_key(|orphan| orphan.written);
+
+        while let Some(mut orphan) = orphans.pop() {
+            let stale = orphan.is_stale();
+            if stale {
+                remove_if_exists(&orphan.path)?;
+            } else {
+                std::fs::rename(&orphan.path, self.recovery_path())?;
+                self.project = orphan.project.take();
+            }
+
+            if stale || orphan.id != self.id {
+                remove_if_exists(&self.path(orphan.id, PROJECT_EXT))?;
+            }
+            if orphan.id != self.id {
+                drop(orphan.lock.take());
+                remove_if_exists(&self.path(orphan.id, LOCK_EXT))?;
+            }
+            if !stale {
+                return Ok(Some(self.recovery_path()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Recovery files whose owner is no longer running, with the lock of
+    /// their owner held, so that no other instance claims them at the same
+    /// time. A file of this instance can only be left by an earlier process
+    /// with the same id.
+    fn orphans(&self) -> Vec<Orphan> {
+        let entries = match std::fs::read_dir(&self.config.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let id = recovery_id(&path)?;
+                let lock = match id == self.id {
+                    true => None,
+                    false => Some(self.take_lock(id)?),
+                };
+
+                Some(Orphan {
+                    id,
+                    written: modified(&path)?,
+                    project: std::fs::read_to_string(self.path(id, PROJECT_EXT))
+                        .ok()
+                        .map(PathBuf::from),
+                    path,
+                    lock,
+                })
+            })
+            .collect()
+    }
+
+    /// Take the lock of another instance, if it's not running
+    fn take_lock(&self, id: u32) -> Option<File> {
+        let file = File::create(self.path(id, LOCK_EXT)).ok()?;
+        match file.try_lock() {
+            Ok(()) => Some(file),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Error(e)) => {
+                eprintln!("WARN: could not check the lock of instance {id}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Stop autosaving, e.g. while the user decides whether to restore the
+    /// recovery file, so that it is not overwritten
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Forget the project being autosaved, when a new one is started
+    pub fn reset(&mut self) {
+        self.project = None;
+        self.pending_events = 0;
+    }
+
+    /// Take note of an event executed in the project
+    pub fn register(&mut self, event: &Event) {
+        if event.undoable() {
+            self.pending_events += 1;
+        }
+    }
+
+    /// Whether the project has changes that need to be autosaved now
+    pub fn is_due(&self) -> bool {
+        if self.paused || self.pending_events == 0 {
+            return false;
+        }
+
+        let by_time = matches!(
+            self.config.interval,
+            Some(interval) if self.last_save.elapsed() >= interval
+        );
+        let by_events = matches!(
+            self.config.max_events,
+            Some(max) if self.pending_events >= max
+        );
+
+        by_time || by_events
+    }
+
+    /// Write the project to the recovery file, along with the path of the
+    /// project file it comes from
+    pub fn save(&mut self, state: &State<WrappedImage>) -> Result<()> {
+        // Even if saving fails, don't try again before the next interval
+        self.last_save = Instant::now();
+        self.pending_events = 0;
+
+        self.hold_lock()?;
+        let bytes = project::encode(state)?;
+        project::save(self.recovery_path(), bytes)?;
+
+        let project_path = self.path(self.id, PROJECT_EXT);
+        match &self.project {
+            Some(project) => std::fs::write(project_path, project.to_string_lossy().as_bytes())?,
+            None => remove_if_exists(&project_path)?,
+        }
+
+        Ok(())
+    }
+
+    /// Take note that the project was explicitly saved by the user to a file,
+    /// so the recovery file is not needed anymore. Even if it's not deleted,
+    /// it won't be offered, since it is older than the project file.
+    pub fn project_saved(&mut self, path: PathBuf) -> Result<()> {
+        self.pending_events = 0;
+        self.project = Some(path);
+        self.discard_recovery()
+    }
+
+    /// Delete the recovery file of this instance
+    pub fn discard_recovery(&self) -> Result<()> {
+        remove_if_exists(&self.recovery_path())?;
+        remove_if_exists(&self.path(self.id, PROJECT_EXT))
+    }
+}
+
+impl Drop for Autosave {
+    /// Release the lock, if this is the autosave that holds it
+    fn drop(&mut self) {
+        if self.lock.take().is_some() {
+            let _ = std::fs::remove_file(self.path(self.id, LOCK_EXT));
+        }
+    }
+}
+
+/// A recovery file left by an instance that is no longer running
+struct Orphan {
+    id: u32,
+    path: PathBuf,
+    written: SystemTime,
+    /// The project file the recovery file was autosaved from
+    project: Option<PathBuf>,
+    /// The lock of the instance that left the file, released when the orphan
+    /// is dropped
+    lock: Option<File>,
+}
+
+impl Orphan {
+    /// Whether the project was saved explicitly after the recovery file
+    fn is_stale(&self) -> bool {
+        let saved = self.project.as_deref().and_then(modified);
+        matches!(saved, Some(saved) if saved >= self.written)
+    }
+}
+
+/// The id of the instance that wrote a recovery file, if the path is one
+fn recovery_id(path: &Path) -> Option<u32> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("recovery-")?
+        .strip_suffix(".tarsila")?
+        .parse()
+        .ok()
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path) -> AutosaveConfig {
+        AutosaveConfig {
+            interval: None,
+            max_events: None,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn only_claims_files_of_instances_not_running() {
+        let dir = std::env::temp_dir().join(format!("tarsila-claim-{}", std::process::id()));
+        let mut running = Autosave::with_id(config(&dir), 1);
+        running.hold_lock().unwrap();
+        std::fs::write(running.recovery_path(), b"running").unwrap();
+        let crashed = Autosave::with_id(config(&dir), 2);
+        std::fs::write(crashed.recovery_path(), b"crashed").unwrap();
+
+        let mut autosave = Autosave::with_id(config(&dir), 3);
+        let claimed = autosave.claim_recovery().unwrap();
+
+        assert_eq!(claimed, Some(autosave.recovery_path()));
+        assert_eq!(std::fs::read(autosave.recovery_path()).unwrap(), b"crashed");
+        assert!(!crashed.recovery_path().exists());
+        assert_eq!(std::fs::read(running.recovery_path()).unwrap(), b"running");
+
+        // Once the first instance exits, its file can be claimed as well
+        let running_path = running.recovery_path();
+        drop(running);
+        let mut other = Autosave::with_id(config(&dir), 4);
+        assert_eq!(other.claim_recovery().unwrap(), Some(other.recovery_path()));
+        assert_eq!(std::fs::read(other.recovery_path()).unwrap(), b"running");
+        assert!(!running_path.exists());
+
+        drop((crashed, autosave, other));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_files_older_than_their_project() {
+        let dir = std::env::temp_dir().join(format!("tarsila-stale-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let project = dir.join("project.tarsila");
+        let crashed = Autosave::with_id(config(&dir), 1);
+        std::fs::write(crashed.recovery_path(), b"crashed").unwrap();
+        let project_file = crashed.path(1, PROJECT_EXT);
+        std::fs::write(project_file, project.to_string_lossy().as_bytes()).unwrap();
+
+        // The project was saved after the recovery file, e.g. because
+        // deleting the recovery file failed
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(&project, b"saved").unwrap();
+
+        let mut autosave = Autosave::with_id(config(&dir), 2);
+        assert_eq!(autosave.claim_recovery().unwrap(), None);
+        assert!(!crashed.recovery_path().exists());
+
+        drop((crashed, autosave));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+
+/// Shows errors and warnings to the user, one at a time, until they are
+/// dismissed
+pub struct ErrorDialog {
+    /// Title and text of each message
+    messages: VecDeque<(&'static str, String)>,
+}
+
+impl ErrorDialog {
+    pub fn new() -> Self {
+        Self {
+            messages: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: String) {
+        self.push_message("Error", message);
+    }
+
+    pub fn push_warning(&mut self, message: String) {
+        self.push_message("Warning", message);
+    }
+
+    fn push_message(&mut self, title: &'static str, message: String) {
+        let message = (title, message);
+
+        // An error that repeats every frame should only be shown once
+        if self.messages.back() != Some(&message) {
+            self.messages.push_back(message);
+        }
+    }
+
+    pub fn update(&mut self, egui_ctx: &egui::Context) {
+        let (title, message) = match self.messages.front() {
+            Some(message) => message,
+            None => return,
+        };
+
+        let mut dismissed = false;
+
+        egui::Window::new(*title)
+            .default_pos((200., 30.))
+            .collapsible(false)
+            .show(egui_ctx, |ui| {
+                ui.label(message);
+                if ui.button("Ok").clicked() {
+                    dismissed = true;
+                }
+            });
+
+        if dismissed {
+            self.messages.pop_front();
+        }
+    }
+}
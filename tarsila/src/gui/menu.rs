@@ -1,17 +1,67 @@
 use crate::{Effect, UiEvent};
-use lapix::{Event, Size, Tool, Transform};
-use std::path::PathBuf;
+use lapix::{AnimationOptions, AtlasLayout, Event, ExportMode, Size, Tool, Transform};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// File formats to which the frames of the spritesheet can be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnimationFormat {
+    Gif,
+    Apng,
+    /// One numbered PNG image per frame
+    PngSequence,
+    /// The spritesheet image and a JSON file describing its frames
+    Atlas,
+}
+
+/// Kind of file written by the export window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportTarget {
+    /// A PNG or JPEG image, with the chosen scale and layers
+    Image,
+    /// The whole project as an Aseprite file, which ignores the image options
+    Aseprite,
+}
+
+/// Which layers are exported to an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayerExport {
+    Visible,
+    All,
+    Each,
+    /// The layers typed by the user
+    Chosen,
+}
 
 pub struct MenuBar {
     last_file: Option<PathBuf>,
     show_resize_window: bool,
     show_spritesheet_window: bool,
+    show_export_window: bool,
+    show_animation_window: bool,
+    show_import_frames_window: bool,
     show_confirm_exit_window: bool,
     show_confirm_new_window: bool,
+    /// Aseprite file waiting for confirmation to replace the current project
+    confirm_open_aseprite: Option<PathBuf>,
     canvas_size: Size<i32>,
     spritesheet: Size<u8>,
+    num_layers: usize,
     canvas_size_str: Option<(String, String)>,
     spritesheet_str: Option<(String, String)>,
+    export_target: ExportTarget,
+    export_scale_str: String,
+    layer_export: LayerExport,
+    /// Comma-separated numbers of the layers to export
+    export_layers_str: String,
+    /// Whether to export a PNG with the colors of the palette
+    export_indexed: bool,
+    animation_format: AnimationFormat,
+    atlas_layout: AtlasLayout,
+    /// Frame delay and number of repetitions
+    animation_str: (String, String),
+    /// Number of columns of the imported frames
+    import_columns_str: String,
 }
 
 impl MenuBar {
@@ -20,26 +70,48 @@ impl MenuBar {
             last_file: None,
             show_resize_window: false,
             show_spritesheet_window: false,
+            show_export_window: false,
+            show_animation_window: false,
+            show_import_frames_window: false,
             show_confirm_exit_window: false,
             show_confirm_new_window: false,
+            confirm_open_aseprite: None,
             canvas_size: Size::ZERO,
             spritesheet: (1, 1).into(),
+            num_layers: 0,
             canvas_size_str: None,
             spritesheet_str: None,
+            export_target: ExportTarget::Image,
+            export_scale_str: "1".to_owned(),
+            layer_export: LayerExport::Visible,
+            export_layers_str: String::new(),
+            export_indexed: false,
+            animation_format: AnimationFormat::Gif,
+            atlas_layout: AtlasLayout::default(),
+            animation_str: (
+                AnimationOptions::default().frame_delay.to_string(),
+                String::new(),
+            ),
+            import_columns_str: String::new(),
         }
     }
 
-    pub fn sync(&mut self, canvas_size: Size<i32>, spritesheet: Size<u8>) {
+    pub fn sync(&mut self, canvas_size: Size<i32>, spritesheet: Size<u8>, num_layers: usize) {
         self.canvas_size = canvas_size;
         self.spritesheet = spritesheet;
+        self.num_layers = num_layers;
     }
 
     pub fn update(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
         let mut events = self.update_menu(egui_ctx);
         events.append(&mut self.update_resize_window(egui_ctx));
         events.append(&mut self.update_spritesheet_window(egui_ctx));
+        events.append(&mut self.update_export_window(egui_ctx));
+        events.append(&mut self.update_animation_window(egui_ctx));
+        events.append(&mut self.update_import_frames_window(egui_ctx));
         events.append(&mut self.update_confirm_exit_window(egui_ctx));
         events.append(&mut self.update_confirm_new_window(egui_ctx));
+        events.append(&mut self.update_confirm_open_aseprite_window(egui_ctx));
         events
     }
 
@@ -57,6 +129,7 @@ impl MenuBar {
                         ui.close_menu();
                         let mut dialog = rfd::FileDialog::new()
                             .add_filter("Tarsila files", &["tarsila"])
+                            .add_filter("Tarsila archives", &["zip"])
                             .add_filter("All files", &["*"]);
 
                         if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
@@ -65,13 +138,18 @@ impl MenuBar {
 
                         if let Some(path) = dialog.save_file() {
                             self.last_file = Some(path.clone());
-                            events.push(Event::SaveProject(path).into());
+                            if is_project_archive(&path) {
+                                events.push(Event::SaveProjectArchive(path).into());
+                            } else {
+                                events.push(Event::SaveProject(path).into());
+                            }
                         }
                     }
                     if ui.button("Load Project").clicked() {
                         ui.close_menu();
                         let mut dialog = rfd::FileDialog::new()
                             .add_filter("Tarsila files", &["tarsila"])
+                            .add_filter("Tarsila archives", &["zip"])
                             .add_filter("All files", &["*"]);
 
                         if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
@@ -80,25 +158,36 @@ impl MenuBar {
 
                         if let Some(path) = dialog.pick_file() {
                             self.last_file = Some(path.clone());
-                            events.push(Event::LoadProject(path).into());
+                            if is_project_archive(&path) {
+                                events.push(Event::LoadProjectArchive(path).into());
+                            } else {
+                                events.push(Event::LoadProject(path).into());
+                            }
                         }
                     }
-                    if ui.button("Export Image").clicked() {
+                    if ui.button("Open Aseprite File").clicked() {
                         ui.close_menu();
                         let mut dialog = rfd::FileDialog::new()
-                            .add_filter("PNG files", &["png"])
-                            .add_filter("JPEG files", &["jpg", "jpeg"])
+                            .add_filter("Aseprite files", &["ase", "aseprite"])
                             .add_filter("All files", &["*"]);
 
                         if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
                             dialog = dialog.set_directory(dir);
                         }
 
-                        if let Some(path) = dialog.save_file() {
+                        if let Some(path) = dialog.pick_file() {
                             self.last_file = Some(path.clone());
-                            events.push(Event::Save(path).into());
+                            self.confirm_open_aseprite = Some(path);
                         }
                     }
+                    if ui.button("Export Image").clicked() {
+                        ui.close_menu();
+                        self.show_export_window = true;
+                    }
+                    if ui.button("Export Animation").clicked() {
+                        ui.close_menu();
+                        self.show_animation_window = true;
+                    }
                     if ui.button("Import Image").clicked() {
                         ui.close_menu();
                         let mut dialog = rfd::FileDialog::new()
@@ -116,6 +205,29 @@ impl MenuBar {
                             events.push(Event::SetTool(Tool::Move).into());
                         }
                     }
+                    if ui.button("Save Palette").clicked() {
+                        ui.close_menu();
+                        let mut dialog = rfd::FileDialog::new()
+                            .add_filter("GIMP palettes", &["gpl"])
+                            .add_filter("JASC palettes", &["pal"])
+                            .add_filter("HEX files", &["hex"])
+                            .add_filter("Paint.NET palettes", &["txt"])
+                            .add_filter("PNG files", &["png"])
+                            .add_filter("All files", &["*"]);
+
+                        if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
+                            dialog = dialog.set_directory(dir).set_file_name("palette.gpl");
+                        }
+
+                        if let Some(path) = dialog.save_file() {
+                            self.last_file = Some(path.clone());
+                            events.push(Event::SavePalette(path).into());
+                        }
+                    }
+                    if ui.button("Import Frames").clicked() {
+                        ui.close_menu();
+                        self.show_import_frames_window = true;
+                    }
                     if ui.button("Exit").clicked() {
                         self.show_confirm_exit_window = true;
                         ui.close_menu();
@@ -283,6 +395,310 @@ impl MenuBar {
         events
     }
 
+    fn update_export_window(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        if !self.show_export_window {
+            return events;
+        }
+
+        let scale = self.export_scale();
+        let mode = self.export_mode();
+
+        egui::Window::new("Export Image")
+            .default_pos((200., 30.))
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("format:");
+                    let target = &mut self.export_target;
+                    ui.radio_value(target, ExportTarget::Image, "image");
+                    ui.radio_value(target, ExportTarget::Aseprite, "Aseprite project");
+                });
+                let is_image = self.export_target == ExportTarget::Image;
+                ui.add_enabled_ui(is_image, |ui| {
+                    ui.horizontal(|ui| {
+                        let label = ui.label("scale:");
+                        ui.add(
+                            egui::widgets::TextEdit::singleline(&mut self.export_scale_str)
+                                .desired_width(30.0),
+                        )
+                        .labelled_by(label.id);
+                        if let (true, Err(msg)) = (is_image, &scale) {
+                            ui.colored_label(egui::Color32::DARK_RED, msg);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("layers:");
+                        let layers = &mut self.layer_export;
+                        ui.radio_value(layers, LayerExport::Visible, "visible");
+                        ui.radio_value(layers, LayerExport::All, "all");
+                        ui.radio_value(layers, LayerExport::Each, "one file each");
+                        ui.radio_value(layers, LayerExport::Chosen, "chosen");
+                    });
+                    if self.layer_export == LayerExport::Chosen {
+                        ui.horizontal(|ui| {
+                            let label = ui.label("layer numbers (e.g. 1, 3):");
+                            ui.add(
+                                egui::widgets::TextEdit::singleline(&mut self.export_layers_str)
+                                    .desired_width(80.0),
+                            )
+                            .labelled_by(label.id);
+                            if let (true, Err(msg)) = (is_image, &mode) {
+                                ui.colored_label(egui::Color32::DARK_RED, msg);
+                            }
+                        });
+                    }
+                    ui.checkbox(&mut self.export_indexed, "indexed PNG (palette colors)");
+                });
+
+                ui.horizontal(|ui| {
+                    let valid = !is_image || (scale.is_ok() && mode.is_ok());
+                    if ui.add_enabled(valid, egui::Button::new("export")).clicked() {
+                        let mut dialog = match self.export_target {
+                            ExportTarget::Image => rfd::FileDialog::new()
+                                .add_filter("PNG files", &["png"])
+                                .add_filter("JPEG files", &["jpg", "jpeg"]),
+                            ExportTarget::Aseprite => rfd::FileDialog::new()
+                                .add_filter("Aseprite files", &["ase", "aseprite"]),
+                        }
+                        .add_filter("All files", &["*"]);
+
+                        if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
+                            dialog = dialog.set_directory(dir);
+                        }
+
+                        if let Some(path) = dialog.save_file() {
+                            self.last_file = Some(path.clone());
+                            match (self.export_target, scale, mode) {
+                                (ExportTarget::Aseprite, ..) => {
+                                    events.push(Event::ExportAseprite(path).into());
+                                    self.show_export_window = false;
+                                }
+                                // The image options would be ignored, so the
+                                // window stays open showing they are disabled
+                                _ if is_aseprite(&path) => {
+                                    self.export_target = ExportTarget::Aseprite;
+                                }
+                                (ExportTarget::Image, Ok(scale), Ok(mode)) => {
+                                    let indexed = self.export_indexed;
+                                    let event = Event::Export {
+                                        path,
+                                        scale,
+                                        mode,
+                                        indexed,
+                                    };
+                                    events.push(event.into());
+                                    self.show_export_window = false;
+                                }
+                                _ => (),
+                            }
+                        } else {
+                            self.show_export_window = false;
+                        }
+                    }
+                    if ui.button("cancel").clicked() {
+                        self.show_export_window = false;
+                    }
+                });
+            });
+
+        events
+    }
+
+    /// Get the scale typed in the export window, or the reason it is invalid
+    fn export_scale(&self) -> Result<u8, String> {
+        match self.export_scale_str.trim().parse::<u8>() {
+            Ok(0) => Err(lapix::Error::InvalidExportScale.to_string()),
+            Ok(scale) => Ok(scale),
+            Err(_) => Err(format!(
+                "The export scale must be a number up to {}",
+                u8::MAX
+            )),
+        }
+    }
+
+    /// Get the export mode chosen in the export window, or the reason the
+    /// layer numbers typed are invalid
+    fn export_mode(&self) -> Result<ExportMode, String> {
+        let mode = match self.layer_export {
+            LayerExport::Visible => ExportMode::VisibleLayers,
+            LayerExport::All => ExportMode::AllLayers,
+            LayerExport::Each => ExportMode::EachLayer,
+            LayerExport::Chosen => {
+                if self.export_layers_str.trim().is_empty() {
+                    return Err("Type the numbers of the layers to export".to_owned());
+                }
+
+                // Layers are numbered from 1 in the UI
+                let indices = self
+                    .export_layers_str
+                    .split(',')
+                    .map(|n| match n.trim().parse::<usize>() {
+                        Ok(num @ 1..) if num <= self.num_layers => Ok(num - 1),
+                        Ok(num) => Err(format!("There is no layer {num}")),
+                        Err(_) => Err(format!("Invalid layer number: {}", n.trim())),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ExportMode::Layers(indices)
+            }
+        };
+
+        Ok(mode)
+    }
+
+    fn update_import_frames_window(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        if !self.show_import_frames_window {
+            return events;
+        }
+
+        egui::Window::new("Import Frames")
+            .default_pos((200., 30.))
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = ui.label("columns (empty for one row):");
+                    ui.add(
+                        egui::widgets::TextEdit::singleline(&mut self.import_columns_str)
+                            .desired_width(40.0),
+                    )
+                    .labelled_by(label.id);
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("choose images").clicked() {
+                        let columns = match self.import_columns_str.trim() {
+                            "" => Ok(None),
+                            n => n.parse::<u8>().map(Some),
+                        };
+
+                        if let Ok(columns) = columns {
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter("PNG files", &["png"])
+                                .add_filter("JPEG files", &["jpg", "jpeg"])
+                                .add_filter("All files", &["*"]);
+
+                            if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
+                                dialog = dialog.set_directory(dir);
+                            }
+
+                            if let Some(mut paths) = dialog.pick_files() {
+                                // Frames are usually numbered in their names
+                                paths.sort();
+                                self.last_file = paths.first().cloned();
+                                let columns = columns
+                                    .unwrap_or_else(|| paths.len().min(u8::MAX as usize) as u8);
+                                events.push(Event::ImportFrames { paths, columns }.into());
+                            }
+                            self.show_import_frames_window = false;
+                        }
+                    }
+                    if ui.button("cancel").clicked() {
+                        self.show_import_frames_window = false;
+                    }
+                });
+            });
+
+        events
+    }
+
+    fn update_animation_window(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        if !self.show_animation_window {
+            return events;
+        }
+
+        egui::Window::new("Export Animation")
+            .default_pos((200., 30.))
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("format:");
+                    let format = &mut self.animation_format;
+                    ui.radio_value(format, AnimationFormat::Gif, "GIF");
+                    ui.radio_value(format, AnimationFormat::Apng, "APNG");
+                    ui.radio_value(format, AnimationFormat::PngSequence, "PNG frames");
+                    ui.radio_value(format, AnimationFormat::Atlas, "spritesheet + JSON");
+                });
+                if self.animation_format == AnimationFormat::Atlas {
+                    ui.horizontal(|ui| {
+                        ui.label("JSON layout:");
+                        let layout = &mut self.atlas_layout;
+                        ui.radio_value(layout, AtlasLayout::Hash, "hash");
+                        ui.radio_value(layout, AtlasLayout::Array, "array");
+                    });
+                }
+                ui.horizontal(|ui| {
+                    let label = ui.label("frame delay (ms):");
+                    ui.add(
+                        egui::widgets::TextEdit::singleline(&mut self.animation_str.0)
+                            .desired_width(40.0),
+                    )
+                    .labelled_by(label.id);
+                });
+                ui.horizontal(|ui| {
+                    let label = ui.label("repeat (empty for forever):");
+                    ui.add(
+                        egui::widgets::TextEdit::singleline(&mut self.animation_str.1)
+                            .desired_width(40.0),
+                    )
+                    .labelled_by(label.id);
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("export").clicked() {
+                        let repeat = match self.animation_str.1.trim() {
+                            "" => Ok(None),
+                            n => n.parse().map(Some),
+                        };
+
+                        if let (Ok(frame_delay), Ok(repeat)) =
+                            (self.animation_str.0.trim().parse(), repeat)
+                        {
+                            let options = AnimationOptions {
+                                frame_delay,
+                                repeat,
+                            };
+                            let (filter, extension) = match self.animation_format {
+                                AnimationFormat::Gif => ("GIF files", "gif"),
+                                AnimationFormat::Apng
+                                | AnimationFormat::PngSequence
+                                | AnimationFormat::Atlas => ("PNG files", "png"),
+                            };
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter(filter, &[extension])
+                                .add_filter("All files", &["*"]);
+
+                            if let Some(dir) = self.last_file.as_ref().and_then(|p| p.parent()) {
+                                dialog = dialog.set_directory(dir);
+                            }
+
+                            if let Some(path) = dialog.save_file() {
+                                self.last_file = Some(path.clone());
+                                let event = match self.animation_format {
+                                    AnimationFormat::Gif => Event::ExportGif(path, options),
+                                    AnimationFormat::Apng => Event::ExportApng(path, options),
+                                    AnimationFormat::PngSequence => Event::ExportFrames(path),
+                                    AnimationFormat::Atlas => Event::ExportAtlas {
+                                        path,
+                                        layout: self.atlas_layout,
+                                        frame_delay,
+                                    },
+                                };
+                                events.push(event.into());
+                            }
+                            self.show_animation_window = false;
+                        }
+                    }
+                    if ui.button("cancel").clicked() {
+                        self.show_animation_window = false;
+                    }
+                });
+            });
+
+        events
+    }
+
     fn update_confirm_exit_window(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
         let mut events = Vec::new();
 
@@ -333,4 +749,48 @@ impl MenuBar {
 
         events
     }
+
+    fn update_confirm_open_aseprite_window(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        let path = match &self.confirm_open_aseprite {
+            Some(path) => path.clone(),
+            None => return events,
+        };
+
+        egui::Window::new("Open Aseprite File")
+            .default_pos((200., 30.))
+            .show(egui_ctx, |ui| {
+                ui.label(
+                    "Opening this file replaces the current project. \
+                    All your unsaved changes will be lost",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Ok").clicked() {
+                        events.push(Event::ImportAseprite(path).into());
+                        self.confirm_open_aseprite = None;
+                    }
+                    if ui.button("cancel").clicked() {
+                        self.confirm_open_aseprite = None;
+                    }
+                });
+            });
+
+        events
+    }
+}
+
+/// Projects are saved and loaded as archives (a zip file with PNG layers and a
+/// manifest) when the file has a `.zip` extension
+fn is_project_archive(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("zip"))
+}
+
+/// Aseprite files hold the whole project (layers and frames) instead of a
+/// single image
+fn is_aseprite(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(OsStr::to_str),
+        Some("ase" | "aseprite")
+    )
 }
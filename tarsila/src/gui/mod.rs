@@ -1,32 +1,40 @@
 use crate::{Effect, UiEvent, UiState};
-use lapix::{Position, Size, Tool};
+use lapix::{BucketOptions, ColorMode, GradientOptions, Position, Size, Tool};
 use macroquad::prelude::*;
 
+mod error_dialog;
 mod layers;
 mod menu;
 mod palette;
 mod preview;
+mod recovery;
 mod status;
 mod toolbar;
 
+use error_dialog::ErrorDialog;
 use layers::LayersPanel;
 use menu::MenuBar;
 use palette::Palette;
 use preview::Preview;
+use recovery::RecoveryDialog;
 use status::StatusBar;
 use toolbar::Toolbar;
 
 #[derive(Debug, Clone)]
 pub struct GuiSyncParams {
     pub main_color: [u8; 4],
+    pub secondary_color: [u8; 4],
     pub num_layers: usize,
     pub active_layer: usize,
     pub layers_vis: Vec<bool>,
     pub layers_alpha: Vec<u8>,
     pub palette: Vec<[u8; 4]>,
+    pub color_mode: ColorMode,
     pub mouse_canvas: Position<i32>,
     pub is_on_canvas: bool,
     pub selected_tool: Tool,
+    pub bucket_options: BucketOptions,
+    pub gradient_options: GradientOptions,
     pub visible_pixel_on_mouse: Option<[u8; 4]>,
     pub canvas_size: Size<i32>,
     pub spritesheet: Size<u8>,
@@ -41,6 +49,8 @@ pub struct Gui {
     palette: Palette,
     status_bar: StatusBar,
     menu: MenuBar,
+    error_dialog: ErrorDialog,
+    recovery_dialog: RecoveryDialog,
     mouse_on_canvas: bool,
     selected_tool: Tool,
 }
@@ -54,6 +64,8 @@ impl Gui {
             palette: Palette::new(),
             status_bar: StatusBar::new(),
             menu: MenuBar::new(),
+            error_dialog: ErrorDialog::new(),
+            recovery_dialog: RecoveryDialog::new(),
             mouse_on_canvas: false,
             selected_tool: Tool::Brush,
         }
@@ -62,7 +74,12 @@ impl Gui {
     pub fn sync(&mut self, params: GuiSyncParams) {
         self.mouse_on_canvas = params.is_on_canvas;
 
-        self.toolbar.sync(params.main_color);
+        self.toolbar.sync(
+            params.main_color,
+            params.secondary_color,
+            params.bucket_options,
+            params.gradient_options,
+        );
         self.selected_tool = params.selected_tool;
         self.layers_panel.sync(
             params.num_layers,
@@ -76,11 +93,26 @@ impl Gui {
             params.layers_vis.clone(),
             params.layers_alpha.clone(),
         );
-        self.palette.sync(params.palette.clone());
-        self.menu.sync(params.canvas_size, params.spritesheet);
+        self.palette.sync(params.palette.clone(), params.color_mode);
+        self.menu
+            .sync(params.canvas_size, params.spritesheet, params.num_layers);
         self.status_bar.sync(params);
     }
 
+    /// Show an error message in a dialog, after the ones already shown
+    pub fn show_error(&mut self, message: String) {
+        self.error_dialog.push(message);
+    }
+
+    pub fn show_warning(&mut self, message: String) {
+        self.error_dialog.push_warning(message);
+    }
+
+    /// Ask the user whether to restore the autosaved work
+    pub fn offer_recovery(&mut self) {
+        self.recovery_dialog.open();
+    }
+
     pub fn update(&mut self) -> Vec<Effect> {
         let mut events = Vec::new();
 
@@ -125,6 +157,10 @@ impl Gui {
 
             self.preview.update(egui_ctx);
             self.status_bar.update(egui_ctx);
+            self.error_dialog.update(egui_ctx);
+
+            let mut recovery_events = self.recovery_dialog.update(egui_ctx);
+            events.append(&mut recovery_events);
 
             let mut canvas_panel_events = self.update_canvas_panel(egui_ctx);
             events.append(&mut canvas_panel_events);
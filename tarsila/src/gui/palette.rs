@@ -1,12 +1,37 @@
 use crate::wrapped_image::WrappedImage;
-use crate::Effect;
-use lapix::{Bitmap, Color, Event};
+use crate::{util, Effect};
+use lapix::{Bitmap, Color, ColorMode, Event, BUILTIN_PALETTES};
 use macroquad::prelude::Image as MqImage;
 
 const BTN_SIZE: i32 = 20;
 
+/// A palette color being edited with its own color picker, so that the main
+/// color (which snaps to the palette in indexed mode) is not involved
+struct ColorEdit {
+    index: usize,
+    color: [u8; 3],
+    alpha: u8,
+}
+
+impl ColorEdit {
+    fn new(index: usize, color: [u8; 4]) -> Self {
+        Self {
+            index,
+            color: util::rgba_to_rgb_u8(color),
+            alpha: color[3],
+        }
+    }
+
+    fn event(&self) -> Event {
+        let [r, g, b] = self.color;
+        Event::EditPaletteColor(self.index, [r, g, b, self.alpha].into())
+    }
+}
+
 pub struct Palette {
     colors: Vec<[u8; 4]>,
+    color_mode: ColorMode,
+    editing: Option<ColorEdit>,
     images: Vec<MqImage>,
     egui_images: Vec<egui::ColorImage>,
     textures: Vec<Option<egui::TextureHandle>>,
@@ -16,6 +41,8 @@ impl Palette {
     pub fn new() -> Self {
         Self {
             colors: Vec::new(),
+            color_mode: ColorMode::Rgba,
+            editing: None,
             images: Vec::new(),
             egui_images: Vec::new(),
             textures: Vec::new(),
@@ -23,7 +50,8 @@ impl Palette {
     }
 
     // TODO: this is a copy and paste of the sync fn in `Preview`, DRY
-    pub fn sync(&mut self, colors: Vec<[u8; 4]>) {
+    pub fn sync(&mut self, colors: Vec<[u8; 4]>, color_mode: ColorMode) {
+        self.color_mode = color_mode;
         if !colors.is_empty() {
             self.colors = colors;
             self.images = self
@@ -51,12 +79,33 @@ impl Palette {
             .show(egui_ctx, |ui| {
                 let btn = ui.button("Load");
                 if btn.clicked() {
-                    let dialog = rfd::FileDialog::new();
+                    let dialog = rfd::FileDialog::new()
+                        .add_filter("All files", &["*"])
+                        .add_filter("Palette files", &["gpl", "pal", "hex", "txt"])
+                        .add_filter("Images", &["png", "jpg", "jpeg"]);
 
                     if let Some(path) = dialog.pick_file() {
                         fx.push(Event::LoadPalette(path).into());
                     }
                 }
+                egui::ComboBox::from_id_source("builtin_palettes")
+                    .selected_text("Built-in palettes")
+                    .show_ui(ui, |ui| {
+                        for name in BUILTIN_PALETTES {
+                            if ui.selectable_label(false, name).clicked() {
+                                fx.push(Event::SetBuiltinPalette(name.to_owned()).into());
+                            }
+                        }
+                    });
+                let mut indexed = self.color_mode == ColorMode::Indexed;
+                if ui.checkbox(&mut indexed, "Indexed colors").changed() {
+                    let mode = if indexed {
+                        ColorMode::Indexed
+                    } else {
+                        ColorMode::Rgba
+                    };
+                    fx.push(Event::SetColorMode(mode).into());
+                }
                 ui.horizontal_wrapped(|ui| {
                     ui.set_max_width(160.);
                     ui.spacing_mut().item_spacing = egui::vec2(0., 0.);
@@ -69,7 +118,7 @@ impl Palette {
                             ui.ctx().load_texture("", image.clone(), Default::default())
                         });
                         let tooltip = format!(
-                            "Select color {:?} (HSV: {}, {:.3}, {:.3}) (right click to remove from palette)",
+                            "Select color {:?} (HSV: {}, {:.3}, {:.3}) (right click to remove from palette, shift click to edit)",
                             self.colors[i],
                             Color::from(self.colors[i]).hue(),
                             Color::from(self.colors[i]).saturation(),
@@ -78,7 +127,9 @@ impl Palette {
 
                         let btn = egui::ImageButton::new(tex, tex.size_vec2());
                         let btn = ui.add(btn).on_hover_text(tooltip);
-                        if btn.clicked() {
+                        if btn.clicked() && ui.input(|i| i.modifiers.shift) {
+                            self.editing = Some(ColorEdit::new(i, self.colors[i]));
+                        } else if btn.clicked() {
                             fx.push(Event::SetMainColor(self.colors[i].into()).into());
                         }
                         if btn.clicked_by(egui::PointerButton::Secondary) {
@@ -86,6 +137,22 @@ impl Palette {
                         }
                     }
                 });
+
+                if let Some(edit) = &mut self.editing {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Edit color {}:", edit.index));
+                        ui.color_edit_button_srgb(&mut edit.color);
+                    });
+                    let (apply, cancel) = ui
+                        .horizontal(|ui| (ui.button("Apply"), ui.button("Cancel")))
+                        .inner;
+                    if apply.clicked() {
+                        fx.push(edit.event().into());
+                    }
+                    if apply.clicked() || cancel.clicked() {
+                        self.editing = None;
+                    }
+                }
             });
 
         fx
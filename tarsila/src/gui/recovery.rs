@@ -0,0 +1,47 @@
+use crate::{Effect, UiEvent};
+
+/// Asks the user whether to restore the work that was autosaved before the
+/// editor was closed
+pub struct RecoveryDialog {
+    show: bool,
+}
+
+impl RecoveryDialog {
+    pub fn new() -> Self {
+        Self { show: false }
+    }
+
+    pub fn open(&mut self) {
+        self.show = true;
+    }
+
+    pub fn update(&mut self, egui_ctx: &egui::Context) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        if !self.show {
+            return events;
+        }
+
+        egui::Window::new("Restore Unsaved Work")
+            .default_pos((200., 30.))
+            .collapsible(false)
+            .show(egui_ctx, |ui| {
+                ui.label(
+                    "Tarsila was closed with unsaved changes. \
+                    Do you want to restore them?",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        events.push(UiEvent::RestoreRecovery.into());
+                        self.show = false;
+                    }
+                    if ui.button("Discard").clicked() {
+                        events.push(UiEvent::DiscardRecovery.into());
+                        self.show = false;
+                    }
+                });
+            });
+
+        events
+    }
+}
@@ -1,11 +1,11 @@
 use crate::{util, Effect, Resources};
 use egui::Color32;
-use lapix::{Event, Size, Tool};
+use lapix::{BucketOptions, Dither, Event, GradientOptions, GradientShape, Size, Tool};
 use macroquad::prelude::*;
 use std::collections::HashMap;
 
 const TOOL_BTN_IMG_SIZE: Size<usize> = Size { x: 16, y: 16 };
-const TOOLS: [Tool; 9] = [
+const TOOLS: [Tool; 10] = [
     Tool::Brush,
     Tool::Bucket,
     Tool::Eraser,
@@ -15,12 +15,16 @@ const TOOLS: [Tool; 9] = [
     Tool::Move,
     Tool::Rectangle,
     Tool::Ellipse,
+    Tool::Gradient,
 ];
 
 pub struct Toolbar {
     tools: HashMap<Tool, ToolButton>,
     brush: [u8; 3],
     brush_alpha: String,
+    bucket: BucketOptions,
+    secondary: [u8; 4],
+    gradient: GradientOptions,
 }
 
 impl Toolbar {
@@ -29,12 +33,24 @@ impl Toolbar {
             tools: TOOLS.iter().map(|t| (*t, ToolButton::new(*t))).collect(),
             brush: [0, 0, 0],
             brush_alpha: "255".to_owned(),
+            bucket: BucketOptions::default(),
+            secondary: [255, 255, 255, 255],
+            gradient: GradientOptions::default(),
         }
     }
 
-    pub fn sync(&mut self, main_color: [u8; 4]) {
+    pub fn sync(
+        &mut self,
+        main_color: [u8; 4],
+        secondary_color: [u8; 4],
+        bucket: BucketOptions,
+        gradient: GradientOptions,
+    ) {
         self.brush = util::rgba_to_rgb_u8(main_color);
         self.brush_alpha = main_color[3].to_string();
+        self.secondary = secondary_color;
+        self.bucket = bucket;
+        self.gradient = gradient;
     }
 
     pub fn get_mut(&mut self, tool: Tool) -> Option<&mut ToolButton> {
@@ -83,10 +99,81 @@ impl Toolbar {
                         }
                     }
                 });
+
+                if selected_tool == Tool::Bucket {
+                    let bucket = &mut self.bucket;
+                    let tolerance = ui
+                        .add(egui::Slider::new(&mut bucket.tolerance, 0..=100).text("tolerance"))
+                        .on_hover_text("how different the filled colors can be (in %)");
+                    let replace_all = ui
+                        .checkbox(&mut bucket.replace_all, "replace all")
+                        .on_hover_text("fill every pixel with the color, not only this area");
+                    let diagonal = ui
+                        .checkbox(&mut bucket.diagonal, "diagonal")
+                        .on_hover_text("fill through pixels that touch diagonally");
+
+                    if tolerance.changed() || replace_all.changed() || diagonal.changed() {
+                        events.push(Event::SetBucketOptions(*bucket).into());
+                    }
+                }
+
+                if selected_tool == Tool::Gradient {
+                    events.append(&mut self.gradient_settings(ui));
+                }
             });
 
         events
     }
+
+    fn gradient_settings(&mut self, ui: &mut egui::Ui) -> Vec<Effect> {
+        let mut events = Vec::new();
+
+        ui.horizontal(|ui| {
+            let mut rgb = util::rgba_to_rgb_u8(self.secondary);
+            let label = ui.label("secondary:");
+            let picker = ui
+                .color_edit_button_srgb(&mut rgb)
+                .labelled_by(label.id)
+                .on_hover_text("color at the end of the gradient");
+            if picker.changed() {
+                let color = [rgb[0], rgb[1], rgb[2], self.secondary[3]];
+                events.push(Event::SetSecondaryColor(color.into()).into());
+            }
+        });
+
+        let gradient = &mut self.gradient;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .radio_value(&mut gradient.shape, GradientShape::Linear, "linear")
+                .changed();
+            changed |= ui
+                .radio_value(&mut gradient.shape, GradientShape::Radial, "radial")
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            for (dither, text) in [
+                (Dither::Bayer2, "2x2"),
+                (Dither::Bayer4, "4x4"),
+                (Dither::Bayer8, "8x8"),
+            ] {
+                changed |= ui
+                    .radio_value(&mut gradient.dither, dither, text)
+                    .on_hover_text("size of the dithering pattern")
+                    .changed();
+            }
+        });
+        changed |= ui
+            .checkbox(&mut gradient.along_palette, "along palette")
+            .on_hover_text("go through the palette colors instead of main to secondary")
+            .changed();
+
+        if changed {
+            events.push(Event::SetGradientOptions(*gradient).into());
+        }
+
+        events
+    }
 }
 
 pub struct ToolButton {
@@ -149,6 +236,7 @@ impl ToolButton {
             Tool::Move => "move tool (M)",
             Tool::Rectangle => "rectangle tool (R)",
             Tool::Ellipse => "ellipse tool (O)",
+            Tool::Gradient => "gradient tool (D)",
         }
     }
 }
@@ -141,6 +141,10 @@ impl KeyBindings {
                 InputEvent::KeyPress(mq::KeyCode::O.into()).into(),
                 Event::SetTool(Tool::Ellipse).into(),
             ),
+            (
+                InputEvent::KeyPress(mq::KeyCode::D.into()).into(),
+                Event::SetTool(Tool::Gradient).into(),
+            ),
             (
                 InputEvent::KeyPress(mq::KeyCode::I.into()).into(),
                 Event::SetTool(Tool::Eyedropper).into(),
@@ -261,6 +265,24 @@ impl KeyBindings {
                 .into(),
                 Event::Undo.into(),
             ),
+            // REDO
+            (
+                vec![
+                    InputEvent::KeyModifier(KeyboardModifier::Control),
+                    InputEvent::KeyPress(mq::KeyCode::Y.into()),
+                ]
+                .into(),
+                Event::Redo.into(),
+            ),
+            (
+                vec![
+                    InputEvent::KeyModifier(KeyboardModifier::Control),
+                    InputEvent::KeyModifier(KeyboardModifier::Shift),
+                    InputEvent::KeyPress(mq::KeyCode::Z.into()),
+                ]
+                .into(),
+                Event::Redo.into(),
+            ),
             // COPY + PASTE
             (
                 vec![
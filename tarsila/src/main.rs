@@ -1,12 +1,12 @@
 use macroquad::prelude::*;
 
+mod autosave;
 mod bg;
 mod error;
 mod graphics;
 mod gui;
 mod input;
 mod mouse;
-mod project;
 mod resource;
 mod ui_state;
 mod util;
@@ -32,15 +32,16 @@ fn window_conf() -> Conf {
 #[macroquad::main(window_conf)]
 async fn main() {
     let mut state = UiState::default();
+    state.offer_recovery();
     let mut frame = 0;
 
     loop {
         if let Err(e) = state.update(frame) {
-            eprintln!("ERROR: {e}");
+            state.show_error(e);
         }
 
         if let Err(e) = state.draw() {
-            eprintln!("ERROR: {e}");
+            state.show_error(e);
         }
 
         next_frame().await;
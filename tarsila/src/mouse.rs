@@ -67,6 +67,7 @@ impl CursorSet {
             (Tool::Move, (-8., -8.).into()),
             (Tool::Rectangle, (0., -16.).into()),
             (Tool::Ellipse, (0., -16.).into()),
+            (Tool::Gradient, (0., -16.).into()),
         ];
 
         let mut hm: HashMap<_, _> = tools
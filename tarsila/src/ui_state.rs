@@ -1,15 +1,18 @@
+use crate::autosave::{Autosave, AutosaveConfig};
 use crate::bg::Background;
+use crate::error::Error;
 use crate::graphics::DrawContext;
 use crate::gui::{Gui, GuiSyncParams};
 use crate::input::bindings::KeyBindings;
 use crate::input::manager::InputManager;
 use crate::mouse::{CursorType, MouseManager};
-use crate::project;
 use crate::wrapped_image::WrappedImage;
 use crate::{graphics, Result, Timer};
 use lapix::primitives::*;
+use lapix::project;
 use lapix::{Canvas, CanvasEffect, Event, Layer, LoadProject, SaveProject, Selection, State, Tool};
 use macroquad::prelude::Color as MqColor;
+use macroquad::prelude::Rect as MqRect;
 use macroquad::prelude::{FilterMode, Texture2D};
 use std::default::Default;
 use std::time::SystemTime;
@@ -77,6 +80,8 @@ pub enum UiEvent {
     ToolEnd,
     BlockCanvas,
     UnblockCanvas,
+    RestoreRecovery,
+    DiscardRecovery,
 }
 
 impl UiEvent {
@@ -100,6 +105,7 @@ impl<'a> From<&'a UiState> for GuiSyncParams {
 
         Self {
             main_color: state.inner.main_color().into(),
+            secondary_color: state.inner.secondary_color().into(),
             num_layers: n_layers,
             active_layer: state.inner.layers().active_index(),
             layers_vis: (0..n_layers)
@@ -109,9 +115,12 @@ impl<'a> From<&'a UiState> for GuiSyncParams {
                 .map(|i| state.inner.layers().get(i).opacity())
                 .collect(),
             palette: state.inner.palette().iter().map(|c| (*c).into()).collect(),
+            color_mode: state.inner.color_mode(),
             mouse_canvas: (x, y).into(),
             is_on_canvas: in_canvas,
             selected_tool: state.selected_tool(),
+            bucket_options: state.inner.bucket_options(),
+            gradient_options: state.inner.gradient_options(),
             visible_pixel_on_mouse: visible_pixel,
             canvas_size: state.canvas().size(),
             spritesheet: state.inner.spritesheet(),
@@ -140,6 +149,7 @@ pub struct UiState {
     fps: f32,
     bg: Background,
     prev_cursor: CursorType,
+    autosave: Autosave,
 }
 
 impl Default for UiState {
@@ -176,6 +186,7 @@ impl Default for UiState {
             bg: Background::new(),
             prev_cursor: CursorType::Tool(Tool::Brush),
             manual_canvas_block: false,
+            autosave: Autosave::new(AutosaveConfig::from_env()),
         }
     }
 }
@@ -185,6 +196,25 @@ impl UiState {
         self.must_exit
     }
 
+    /// If another instance left autosaved work that was not saved explicitly,
+    /// ask the user whether to restore it. Autosave is paused until the user
+    /// decides.
+    pub fn offer_recovery(&mut self) {
+        match self.autosave.claim_recovery() {
+            Ok(Some(_)) => {
+                self.autosave.pause();
+                self.gui.offer_recovery();
+            }
+            Ok(None) => (),
+            Err(e) => self.show_error(e),
+        }
+    }
+
+    /// Show an error to the user in a dialog
+    pub fn show_error(&mut self, error: Error) {
+        self.gui.show_error(error.to_string());
+    }
+
     /*pub fn drawing_mut(&mut self) -> &mut Texture2D {
         &mut self.layer_textures[self.inner.layers().active_index()]
     }*/
@@ -216,6 +246,10 @@ impl UiState {
 
         self.sync_mouse();
 
+        if self.autosave.is_due() {
+            self.autosave.save(&self.inner)?;
+        }
+
         Ok(())
     }
 
@@ -304,8 +338,20 @@ impl UiState {
     }
 
     pub fn execute(&mut self, event: Event) -> Result<()> {
+        self.autosave.register(&event);
+        let saved_path = match &event {
+            Event::SaveProject(path) | Event::SaveProjectArchive(path) => Some(path.clone()),
+            _ => None,
+        };
         let effect = self.inner.execute(event)?;
 
+        for warning in self.inner.take_warnings() {
+            self.gui.show_warning(warning);
+        }
+        if let Some(path) = saved_path {
+            self.autosave.project_saved(path)?;
+        }
+
         match effect {
             // TODO: Texture2D is copy, so we don't need `drawing_mut` here, but
             // it would be better.
@@ -315,6 +361,11 @@ impl UiState {
             CanvasEffect::New | CanvasEffect::Layer => {
                 self.sync_layer_textures();
             }
+            CanvasEffect::Pixels(dirty) => {
+                for layer in dirty.layers {
+                    self.sync_layer_texture_area(layer, dirty.rect);
+                }
+            }
             CanvasEffect::None => (),
         };
 
@@ -338,6 +389,28 @@ impl UiState {
         }
     }
 
+    /// Upload only an area of a layer's image to its texture. If the area is
+    /// not inside the layer, or the texture doesn't have the size of the layer
+    /// (e.g. it was resized in the same frame), the whole texture is replaced.
+    pub fn sync_layer_texture_area(&mut self, index: usize, rect: Rect<i32>) {
+        let canvas = self.inner.layers().canvas_at(index);
+        let in_bounds = rect.clip_to(canvas.rect()) == rect;
+        let same_size = matches!(
+            self.layer_textures.get(index),
+            Some(tex) if tex.width() as i32 == canvas.width()
+                && tex.height() as i32 == canvas.height()
+        );
+
+        if !in_bounds || !same_size {
+            self.sync_layer_texture(index);
+        } else if rect.w > 0 && rect.h > 0 {
+            let layer_img = &self.inner.layers().canvas_at(index).inner().0;
+            let mq_rect = MqRect::new(rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32);
+            let area = layer_img.sub_image(mq_rect);
+            self.layer_textures[index].update_part(&area, rect.x, rect.y, rect.w, rect.h);
+        }
+    }
+
     pub fn process_event(&mut self, event: UiEvent) -> Result<()> {
         if event.is_gui_interaction() {
             self.gui_interaction_rest.start(GUI_REST_MS);
@@ -363,7 +436,21 @@ impl UiState {
                 self.execute(Event::Paste(p))?;
             }
             UiEvent::Exit => self.must_exit = true,
-            UiEvent::NewProject => *self = UiState::default(),
+            UiEvent::NewProject => {
+                // Keep the autosave, which holds the lock of this instance
+                let mut state = UiState::default();
+                std::mem::swap(&mut state.autosave, &mut self.autosave);
+                state.autosave.reset();
+                *self = state;
+            }
+            UiEvent::RestoreRecovery => {
+                self.autosave.resume();
+                self.execute(Event::LoadProject(self.autosave.recovery_path()))?;
+            }
+            UiEvent::DiscardRecovery => {
+                self.autosave.resume();
+                self.autosave.discard_recovery()?;
+            }
             UiEvent::SetPreviousCursor => self.mouse.set_cursor(self.prev_cursor),
             UiEvent::SetCursor(c) => {
                 self.prev_cursor = self.mouse.cursor();
@@ -387,6 +474,7 @@ impl UiState {
                 (Tool::Rectangle, false) => self.execute(Event::RectStart(p))?,
                 (Tool::Ellipse, false) => self.execute(Event::EllipseStart(p))?,
                 (Tool::Bucket, false) => self.execute(Event::Bucket(p))?,
+                (Tool::Gradient, false) => self.execute(Event::GradientStart(p))?,
                 (Tool::Selection, false) => self.execute(Event::StartSelection(p))?,
                 (Tool::Move, false) => self.execute(Event::MoveStart(p))?,
                 (Tool::Eyedropper, false) => {
@@ -409,6 +497,7 @@ impl UiState {
                 (Tool::Line, false) => self.execute(Event::LineEnd(p))?,
                 (Tool::Rectangle, false) => self.execute(Event::RectEnd(p))?,
                 (Tool::Ellipse, false) => self.execute(Event::EllipseEnd(p))?,
+                (Tool::Gradient, false) => self.execute(Event::GradientEnd(p))?,
                 (Tool::Selection, false) => {
                     self.execute(Event::EndSelection(p))?;
                     self.execute(Event::SetTool(Tool::Move))?;